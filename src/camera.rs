@@ -0,0 +1,175 @@
+use game2d::{game::common::{DeltaTime, Position, Position2d, Size2d, WithPosition, WithSize}, graphics::graphics::Graphics};
+
+use crate::{GAME_WINDOW_HEIGHT, GAME_WINDOW_WIDTH};
+
+pub const CAMERA_DEAD_ZONE: Size2d = Size2d { w: 96, h: 64 };
+pub const CAMERA_SMOOTHING: f32 = 8.;
+
+// ################################################################################################################
+// #                                         D R A W   W I T H   C A M E R A                                      #
+// ################################################################################################################
+pub trait CameraDrawable {
+    fn draw_with_camera(&mut self, graphics: &mut Graphics, camera: &Camera);
+}
+
+// ################################################################################################################
+// #                                                 C A M E R A                                                  #
+// ################################################################################################################
+pub struct Camera {
+    position: Position2d,
+    size: Size2d,
+    dead_zone: Size2d,
+    smoothing: f32,
+    bounds: Option<Size2d>,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: Position2d { x: 0., y: 0. },
+            size: Size2d { w: GAME_WINDOW_WIDTH, h: GAME_WINDOW_HEIGHT },
+            dead_zone: CAMERA_DEAD_ZONE,
+            smoothing: CAMERA_SMOOTHING,
+            bounds: None,
+        }
+    }
+}
+
+impl Camera {
+    /*
+     * new()
+     *
+     * @brief : Create a camera showing width x height pixels of the world
+     */
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            size: Size2d { w: width, h: height },
+            ..Default::default()
+        }
+    }
+
+    pub fn get_position(&self) -> &Position2d {
+        &self.position
+    }
+
+    pub fn get_size(&self) -> &Size2d {
+        &self.size
+    }
+
+    /*
+     * set_dead_zone()
+     *
+     * @brief : Box (centered on the screen) where the target moves without scrolling
+     */
+    pub fn set_dead_zone(&mut self, dead_zone: Size2d) {
+        self.dead_zone = dead_zone;
+    }
+
+    /*
+     * set_smoothing()
+     *
+     * @brief : Speed to reach the wanted position (0 = no smoothing)
+     */
+    pub fn set_smoothing(&mut self, smoothing: f32) {
+        self.smoothing = smoothing;
+    }
+
+    /*
+     * set_bounds()
+     *
+     * @brief : Size of the world (in pixels) the camera must not leave
+     */
+    pub fn set_bounds(&mut self, bounds: Option<Size2d>) {
+        self.bounds = bounds;
+        self.position = self.clamp(self.position);
+    }
+
+    /*
+     * look_at()
+     *
+     * @brief : Center the camera on the target, without smoothing
+     */
+    pub fn look_at<T: WithPosition + WithSize + ?Sized>(&mut self, target: &T) {
+        let center = Camera::center_of(target.get_position(), target.get_size());
+        self.position = self.clamp(Position2d {
+            x: center.x - self.size.w as Position / 2.,
+            y: center.y - self.size.h as Position / 2.,
+        });
+    }
+
+    /*
+     * follow()
+     *
+     * @brief : Move the camera toward the target (position / size) when it leaves the dead zone
+     */
+    pub fn follow(&mut self, position: &Position2d, size: &Size2d, dt: &DeltaTime) {
+        let center = Camera::center_of(position, size);
+        let mut wanted = self.position;
+
+        // Dead zone in world coordinates
+        let left = self.position.x + (self.size.w as Position - self.dead_zone.w as Position) / 2.;
+        let top = self.position.y + (self.size.h as Position - self.dead_zone.h as Position) / 2.;
+        let right = left + self.dead_zone.w as Position;
+        let bottom = top + self.dead_zone.h as Position;
+
+        if center.x < left {
+            wanted.x -= left - center.x;
+        } else if center.x > right {
+            wanted.x += center.x - right;
+        }
+        if center.y < top {
+            wanted.y -= top - center.y;
+        } else if center.y > bottom {
+            wanted.y += center.y - bottom;
+        }
+        let wanted = self.clamp(wanted);
+
+        // Smoothing (independent of the frame rate)
+        let factor = if self.smoothing > 0. { 1. - (-self.smoothing * dt).exp() } else { 1. };
+        self.position = self.clamp(Position2d {
+            x: self.position.x + (wanted.x - self.position.x) * factor,
+            y: self.position.y + (wanted.y - self.position.y) * factor,
+        });
+    }
+
+    /*
+     * to_world()
+     *
+     * @brief : Convert a position on the screen to a position in the world
+     */
+    pub fn to_world(&self, x: Position, y: Position) -> Position2d {
+        Position2d { x: x + self.position.x, y: y + self.position.y }
+    }
+
+    /*
+     * to_screen()
+     *
+     * @brief : Convert a position in the world to a position on the screen
+     */
+    pub fn to_screen(&self, position: Position2d) -> Position2d {
+        Position2d { x: (position.x - self.position.x).round(), y: (position.y - self.position.y).round() }
+    }
+
+    fn center_of(position: &Position2d, size: &Size2d) -> Position2d {
+        Position2d { x: position.x + size.w as Position / 2., y: position.y + size.h as Position / 2. }
+    }
+
+    fn clamp(&self, position: Position2d) -> Position2d {
+        match &self.bounds {
+            Some(bounds) => Position2d {
+                x: Camera::clamp_axis(position.x, self.size.w, bounds.w),
+                y: Camera::clamp_axis(position.y, self.size.h, bounds.h),
+            },
+            None => position,
+        }
+    }
+
+    fn clamp_axis(value: Position, view: u32, world: u32) -> Position {
+        if world <= view {
+            // World smaller than the screen : no scrolling
+            0.
+        } else {
+            value.clamp(0., (world - view) as Position)
+        }
+    }
+}
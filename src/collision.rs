@@ -0,0 +1,88 @@
+use game2d::game::common::{Position, Position2d, WithPosition, WithSize};
+
+// ################################################################################################################
+// #                                                  A A B B                                                     #
+// ################################################################################################################
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Aabb {
+    pub x: Position,
+    pub y: Position,
+    pub w: Position,
+    pub h: Position,
+}
+
+impl Aabb {
+    /*
+     * from_entity()
+     *
+     * @brief : Box of an entity (position / size)
+     */
+    pub fn from_entity<T: WithPosition + WithSize + ?Sized>(entity: &T) -> Self {
+        let position = entity.get_position();
+        let size = entity.get_size();
+        Self { x: position.x, y: position.y, w: size.w as Position, h: size.h as Position }
+    }
+
+    pub fn left(&self) -> Position {
+        self.x
+    }
+
+    pub fn right(&self) -> Position {
+        self.x + self.w
+    }
+
+    pub fn top(&self) -> Position {
+        self.y
+    }
+
+    pub fn bottom(&self) -> Position {
+        self.y + self.h
+    }
+
+    pub fn get_position(&self) -> Position2d {
+        Position2d { x: self.x, y: self.y }
+    }
+}
+
+// ################################################################################################################
+// #                                               C O N T A C T                                                  #
+// ################################################################################################################
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    // Unit vector from the tile to the entity : (0, -1) = ground, (0, 1) = ceiling, (-1, 0) / (1, 0) = walls
+    // (a slope gives a ground normal leaning to its low side)
+    pub normal: Position2d,
+    // Part of the move done before the impact (0..1), 0 when the entity was already inside the tile
+    pub time: f32,
+    // Depth of the entity inside the tile before it was pushed out (0 when it was outside)
+    pub penetration: Position,
+}
+
+impl Contact {
+    pub fn is_ground(&self) -> bool {
+        self.normal.y < 0.
+    }
+
+    pub fn is_ceiling(&self) -> bool {
+        self.normal.y > 0.
+    }
+
+    pub fn is_wall(&self) -> bool {
+        self.normal.y == 0. && self.normal.x != 0.
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SweepOptions {
+    // One-way platforms do not stop the box
+    pub drop_through: bool,
+    // The box was standing before the move : it climbs the edge at the top of a slope
+    // and sticks to the slopes going down
+    pub grounded: bool,
+}
+
+pub struct SweepResult {
+    // Box after the move, outside of all solid tiles
+    pub aabb: Aabb,
+    pub contacts: Vec<Contact>,
+}
@@ -0,0 +1,65 @@
+use std::rc::Rc;
+
+use game2d::{game::common::{DeltaTime, Position2d, Transformation}, graphics::{graphics::Graphics, images::{Image, ImageInformations}}};
+
+use crate::{camera::{Camera, CameraDrawable}, level::MAP_TILE_SIZE};
+
+// Time (seconds) a splash is shown, and each of its images
+const SPLASH_TIME: f32 = 0.3;
+const SPLASH_FRAME_TIME: f32 = 0.1;
+const SPLASH_FILENAMES: [&str; 3] = ["images/effects/splash1.png", "images/effects/splash2.png", "images/effects/splash3.png"];
+
+// Images of a splash, loaded once and shared by all the splashes
+pub type SplashFrames = Rc<Vec<Image>>;
+
+// ################################################################################################################
+// #                                                 S P L A S H                                                  #
+// ################################################################################################################
+pub struct Splash {
+    position: Position2d,
+    time: f32,
+    frames: SplashFrames,
+}
+
+impl Splash {
+    /*
+     * load_frames()
+     *
+     * @brief : Load the images of a splash (once, when the game is loaded)
+     */
+    pub fn load_frames(graphics: &mut Graphics) -> Result<SplashFrames, String> {
+        SPLASH_FILENAMES.iter()
+            .map(|filename| graphics.new_image(filename).map_err(|e| format!("Splash : unable to load {} ({})", filename, e)))
+            .collect::<Result<Vec<Image>, String>>()
+            .map(Rc::new)
+    }
+
+    /*
+     * new()
+     *
+     * @brief : Splash of an entity entering a liquid, at position (its top-left corner)
+     */
+    pub fn new(frames: &SplashFrames, position: Position2d) -> Self {
+        Self { position, time: SPLASH_TIME, frames: Rc::clone(frames) }
+    }
+
+    pub fn update(&mut self, dt: &DeltaTime) {
+        self.time -= dt;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.time <= 0.
+    }
+}
+
+impl CameraDrawable for Splash {
+    fn draw_with_camera(&mut self, graphics: &mut Graphics, camera: &Camera) {
+        let position = camera.to_screen(self.position);
+        let frame = ((SPLASH_TIME - self.time) / SPLASH_FRAME_TIME) as usize;
+        if let Some(image) = self.frames.get(frame).or(self.frames.last()) {
+            let scalex = MAP_TILE_SIZE as Transformation / image.get_width() as Transformation;
+            let scaley = MAP_TILE_SIZE as Transformation / image.get_height() as Transformation;
+            graphics.draw_full(image, position.x, position.y, 0., scalex, scaley, 0., 0.);
+        }
+    }
+}
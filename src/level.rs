@@ -1,11 +1,25 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Display, fs, path::Path};
 
-use game2d::{game::common::{Size, Position, Position2d, WithPosition, WithSize, Transformation}, graphics::{graphics::Drawable, images::ImageInformations}};
+use game2d::{game::common::{Size, Size2d, Position, Position2d, Velocity2d, WithPosition, WithSize, Transformation}, graphics::{graphics::{Drawable, Graphics}, images::{Image, ImageInformations}}};
+
+use crate::{camera::{Camera, CameraDrawable}, collision::{Aabb, Contact, SweepOptions, SweepResult}, tileset, tiled};
 
 pub type MapLevel = Vec<Vec<char>>;
+// Image of each char of a level
+type MapTextures<T> = HashMap<char, T>;
 
 pub const MAP_TILE_SIZE: f32 = 32.;
+const MAP_PUSH_OUT_ITERATIONS: usize = 4;
+// Smallest step climbed at the top of a slope, and deepest a box can be in a slope to be put back on it
+const MAP_SLOPE_STEP: f32 = MAP_TILE_SIZE / 2.;
+// Farthest the top of a box can be below a ledge corner to grab it
+const MAP_LEDGE_REACH: f32 = 8.;
+pub const MAP_EMPTY: char = '0';
+pub const MAP_PLAYER_START: char = 'P';
+pub const MAP_LEVELS_DIRECTORY: &str = "levels";
+pub const MAP_LEVELS_EXTENSIONS: [&str; 3] = ["txt", "tmx", "tmj"];
 
+#[derive(Clone, Copy, PartialEq)]
 pub enum MapElementCollideType {
     Right,
     Left,
@@ -13,14 +27,143 @@ pub enum MapElementCollideType {
     Above,
 }
 
-#[derive(Default)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MapPowerUp {
+    DoubleJump,
+    Dash,
+}
+
+impl MapPowerUp {
+    /*
+     * from_name()
+     * 
+     * @brief : Power-up from its name in the level files ("double_jump" or "dash")
+     */
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "double_jump" => Some(MapPowerUp::DoubleJump),
+            "dash" => Some(MapPowerUp::Dash),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapLiquid {
+    // Part of the gravity kept in the liquid (negative = floats up)
+    pub gravity_scale: f32,
+    // Part of the velocity lost per second
+    pub drag: f32,
+    // Upward speed (pixels / second) of a swim stroke
+    pub stroke: f32,
+    // Time (seconds) an entity can stay under the liquid (0 = no limit)
+    pub breath_time: f32,
+}
+
+impl Default for MapLiquid {
+    fn default() -> Self {
+        Self { gravity_scale: 0.3, drag: 2., stroke: 220., breath_time: 0. }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapSurface {
+    // Multiplier of the friction of the entities standing on it (< 1 slippery, > 1 sticky)
+    pub friction: f32,
+    // Speed (pixels / second) the surface moves the entities standing on it (< 0 to the left)
+    pub conveyor: f32,
+    // Part of the falling speed given back upward on landing (0 = no bounce)
+    pub bounce: f32,
+}
+
+impl Default for MapSurface {
+    fn default() -> Self {
+        Self { friction: 1., conveyor: 0., bounce: 0. }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MapHazard {
+    // Health points lost on contact
+    Hurt(u32),
+    Kill,
+}
+
+impl MapHazard {
+    /*
+     * from_name()
+     * 
+     * @brief : Hazard from its name in the level files ("hurt" with its damage, or "kill")
+     */
+    pub fn from_name(name: &str, damage: u32) -> Option<Self> {
+        match name {
+            "hurt" => Some(MapHazard::Hurt(damage)),
+            "kill" => Some(MapHazard::Kill),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct MapElement {
     pub name: String,
     pub filename: String,
     pub solid: bool,
+    pub one_way: bool,
+    pub slope: Option<[f32; 2]>,
+    pub climbable: bool,
+    pub hazard: Option<MapHazard>,
+    pub checkpoint: bool,
+    // Image of an activated checkpoint
+    pub active_filename: Option<String>,
+    // Ability given (once) by the tile
+    pub power_up: Option<MapPowerUp>,
+    pub liquid: Option<MapLiquid>,
+    pub surface: Option<MapSurface>,
+    pub exit: bool,
+    pub properties: HashMap<String, String>,
 }
 
-#[derive(Default)]
+impl MapElement {
+    /*
+     * get_slope_height()
+     * 
+     * @brief : Height of the ground of a slope (pixels from the bottom of the tile) at x (pixels from its left)
+     */
+    pub fn get_slope_height(&self, x: Position) -> Option<Position> {
+        self.slope.map(|[left, right]| {
+            let x = x.clamp(0., MAP_TILE_SIZE) / MAP_TILE_SIZE;
+            (left + (right - left) * x) * MAP_TILE_SIZE
+        })
+    }
+
+    /*
+     * validate()
+     * 
+     * @brief : Check the properties of the element go together (same rules for every level format)
+     */
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(slope) = self.slope {
+            if self.solid || slope.iter().any(|h| !(0. ..=1.).contains(h)) {
+                return Err("slope must be heights between 0 and 1, on a tile not solid".to_string());
+            }
+        }
+        if let Some(liquid) = &self.liquid {
+            if self.solid || liquid.drag < 0. || liquid.stroke < 0. || liquid.breath_time < 0. {
+                return Err("liquid must have drag, stroke and breath time >= 0, on a tile not solid".to_string());
+            }
+        }
+        if let Some(surface) = &self.surface {
+            if !(self.solid || self.one_way) || surface.friction <= 0. || !(0. ..=1.).contains(&surface.bounce) {
+                return Err("surface must have friction > 0 and bounce between 0 and 1, on a solid or one-way tile".to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct MapCoord {
     pub lig: isize,
     pub col: isize,
@@ -44,17 +187,35 @@ impl MapCoord {
     }
 }
 
+// Last checkpoint touched, with the level as it was then
+struct MapCheckpoint {
+    coord: MapCoord,
+    level: MapLevel,
+}
+
 pub struct Map {
+    // Elements of the tileset, and the ones of the actual level (tileset + Tiled tiles)
+    tileset: HashMap<char, MapElement>,
     elements: HashMap<char, MapElement>,
     level: MapLevel,
+    // Level as loaded (power-ups not taken)
+    start_level: MapLevel,
+    textures: MapTextures<Image>,
+    active_textures: MapTextures<Image>,
+    checkpoint: Option<MapCheckpoint>,
     pub player_start: Option<Position2d>,
 }
 
 impl Default for Map {
     fn default() -> Self {
         Self { 
+            tileset: HashMap::new(),
             elements: HashMap::new(), 
             level: MapLevel::default(), 
+            start_level: MapLevel::default(),
+            textures: HashMap::new(),
+            active_textures: HashMap::new(),
+            checkpoint: None,
             player_start: None,
         }
     }
@@ -67,37 +228,226 @@ impl Map {
      * @brief : Create a new MAP
      */
     pub fn new() -> Self {
-
-        // Create elements of map
-        let mut elements = HashMap::new();
-
-        // #### WALL1
-        elements.insert('1', 
-            MapElement {
-                name: "Wall1".to_string(),
-                filename: "images/tile1.png".to_string(),
-                solid: true,
-            }
-        );
-
         Self { 
-            elements,
+            elements: HashMap::new(),
             level: Vec::new(), 
             ..Default::default()
         }
     }
 
+    /*
+     * load_tileset()
+     * 
+     * @brief : Replace elements of map by the ones of a tileset file
+     */
+    pub fn load_tileset(&mut self, filename: &str) -> Result<(), String> {
+        self.tileset = tileset::load_tileset(filename)?;
+        self.elements = self.tileset.clone();
+        Ok(())
+    }
+
+    /*
+     * level_filename()
+     * 
+     * @brief: Filename of a level (levels/NNN.txt, or Tiled levels/NNN.tmx / levels/NNN.tmj)
+     */
+    pub fn level_filename(level: i32) -> String {
+        let filename = format!("{}/{:03}", MAP_LEVELS_DIRECTORY, level);
+
+        MAP_LEVELS_EXTENSIONS.iter()
+            .map(|extension| format!("{}.{}", filename, extension))
+            .find(|filename| Path::new(filename).is_file())
+            .unwrap_or(format!("{}.{}", filename, MAP_LEVELS_EXTENSIONS[0]))
+    }
+
     /*
      * load_level()
      * 
-     * @brief: Load a new level
+     * @brief: Load a new level from its file (the map is left as it was on error)
      */
-    pub fn load_level(&mut self, level: i32) {
-        match level {
-           1 => { self.level = level_1(); }
-           _ => { self.level = Vec::new(); }
+    pub fn load_level(&mut self, graphics: &mut Graphics, level: i32) -> Result<(), String> {
+        let filename = Map::level_filename(level);
+
+        let (elements, map_level, player_start) = if filename.ends_with(".txt") {
+            let content = fs::read_to_string(&filename)
+                .map_err(|e| format!("Level {} : unable to read {} ({})", level, filename, e))?;
+
+            let (map_level, player_start) = self.parse_level(&content)
+                .map_err(|e| format!("Level {} : {} in {}", level, e, filename))?;
+            (self.tileset.clone(), map_level, player_start)
+        } else {
+            let tiled_level = tiled::load_tiled(&filename)
+                .map_err(|e| format!("Level {} : {}", level, e))?;
+
+            let elements = self.with_level_elements(tiled_level.elements)
+                .map_err(|e| format!("Level {} : {} in {}", level, e, filename))?;
+            (elements, tiled_level.level, tiled_level.player_start)
         };
-        self.player_start = Some(MapCoord::to_position2d(MapCoord{lig: 14, col: 2}));
+        let (textures, active_textures) = Map::load_textures(&elements, &map_level, |filename| graphics.new_image(filename))
+            .map_err(|e| format!("Level {} : {}", level, e))?;
+
+        // Everything loaded (textures included) : the map changes only now
+        self.elements = elements;
+        self.start_level = map_level.clone();
+        self.level = map_level;
+        self.player_start = Some(player_start);
+        self.textures = textures;
+        self.active_textures = active_textures;
+        self.checkpoint = None;
+
+        Ok(())
+    }
+
+    /*
+     * with_level_elements()
+     * 
+     * @brief: Elements of the tileset with the ones of a level, a char of the tileset can not be replaced
+     */
+    fn with_level_elements(&self, level_elements: HashMap<char, MapElement>) -> Result<HashMap<char, MapElement>, String> {
+        let mut elements = self.tileset.clone();
+
+        for (id, element) in level_elements {
+            if let Some(tile) = elements.get(&id) {
+                return Err(format!("id '{}' of {} already used by {} of the tileset", id, element.name, tile.name));
+            }
+            elements.insert(id, element);
+        }
+
+        Ok(elements)
+    }
+
+    /*
+     * load_textures()
+     * 
+     * @brief: Load once the image of each element used by the level (and the active image of a checkpoint)
+     */
+    fn load_textures<T, E: Display>(elements: &HashMap<char, MapElement>, level: &MapLevel, mut new_image: impl FnMut(&str) -> Result<T, E>)
+        -> Result<(MapTextures<T>, MapTextures<T>), String> {
+        let mut textures = HashMap::new();
+        let mut active_textures = HashMap::new();
+
+        for c in level.iter().flatten() {
+            if textures.contains_key(c) {
+                continue;
+            }
+            if let Some(element) = elements.get(c) {
+                let image = new_image(&element.filename)
+                    .map_err(|e| format!("unable to load {} ({})", element.filename, e))?;
+                textures.insert(*c, image);
+
+                if let Some(filename) = &element.active_filename {
+                    let image = new_image(filename)
+                        .map_err(|e| format!("unable to load {} ({})", filename, e))?;
+                    active_textures.insert(*c, image);
+                }
+            }
+        }
+
+        Ok((textures, active_textures))
+    }
+
+    /*
+     * parse_level()
+     * 
+     * @brief: Convert text (one row per line) to a level, checking every char is in the tileset
+     *         The start of the player (MAP_PLAYER_START) must be present once, it becomes empty space
+     */
+    pub fn parse_level(&self, content: &str) -> Result<(MapLevel, Position2d), String> {
+        let mut level = MapLevel::new();
+        let mut player_start: Option<(MapCoord, usize)> = None;
+
+        for (pos_l, line) in content.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut row: Vec<char> = line.chars().collect();
+            if let Some(first) = level.first() {
+                if row.len() != first.len() {
+                    return Err(format!("line {} has {} columns, expected {}", pos_l + 1, row.len(), first.len()));
+                }
+            }
+
+            // Start of the player
+            for (pos_c, c) in row.iter_mut().enumerate().filter(|(_, c)| **c == MAP_PLAYER_START) {
+                if let Some((start, start_line)) = &player_start {
+                    return Err(format!("second start '{}' at line {}, column {} (first at line {}, column {})", 
+                        MAP_PLAYER_START, pos_l + 1, pos_c + 1, start_line, start.col + 1));
+                }
+                player_start = Some((MapCoord { lig: level.len() as isize, col: pos_c as isize }, pos_l + 1));
+                *c = MAP_EMPTY;
+            }
+
+            if let Some((pos_c, c)) = row.iter().enumerate().find(|(_, c)| **c != MAP_EMPTY && !self.tileset.contains_key(c)) {
+                return Err(format!("unknown element '{}' at line {}, column {}", c, pos_l + 1, pos_c + 1));
+            }
+            level.push(row);
+        }
+
+        if level.is_empty() {
+            return Err("level is empty".to_string());
+        }
+        let (player_start, _) = player_start.ok_or(format!("no start '{}'", MAP_PLAYER_START))?;
+
+        Ok((level, MapCoord::to_position2d(player_start)))
+    }
+
+    /*
+     * get_pixel_size()
+     * 
+     * @brief: Size of the level in pixels
+     */
+    pub fn get_pixel_size(&self) -> Size2d {
+        let lines = self.level.len();
+        let columns = self.level.first().map(|l| l.len()).unwrap_or(0);
+
+        Size2d { w: columns as Size * MAP_TILE_SIZE as Size, h: lines as Size * MAP_TILE_SIZE as Size }
+    }
+
+    /*
+     * get_visible_coords()
+     * 
+     * @brief: First and last tiles (included) seen by the camera
+     */
+    pub fn get_visible_coords(&self, camera: &Camera) -> (MapCoord, MapCoord) {
+        let lines = self.level.len() as isize;
+        let columns = self.level.first().map(|l| l.len()).unwrap_or(0) as isize;
+
+        let top_left = *camera.get_position();
+        let bottom_right = Position2d {
+            x: top_left.x + camera.get_size().w as Position - 1.,
+            y: top_left.y + camera.get_size().h as Position - 1.,
+        };
+        let first = MapCoord::from(top_left);
+        let last = MapCoord::from(bottom_right);
+
+        (
+            MapCoord { lig: first.lig.max(0), col: first.col.max(0) },
+            MapCoord { lig: last.lig.min(lines - 1), col: last.col.min(columns - 1) },
+        )
+    }
+
+    /*
+     * get_visible_tiles()
+     * 
+     * @brief: Coords and chars of the tiles seen by the camera (the only ones drawn)
+     */
+    pub fn get_visible_tiles(&self, camera: &Camera) -> impl Iterator<Item = (MapCoord, char)> + '_ {
+        let (first, last) = self.get_visible_coords(camera);
+
+        (first.lig..=last.lig).flat_map(move |lig| {
+            (first.col..=last.col).map(move |col| (MapCoord { lig, col }, self.level[lig as usize][col as usize]))
+        })
+    }
+
+    /*
+     * level_exists()
+     * 
+     * @brief: Check if the file of a level is present
+     */
+    pub fn level_exists(level: i32) -> bool {
+        Path::new(&Map::level_filename(level)).is_file()
     }
 
     /*
@@ -120,114 +470,1075 @@ impl Map {
     }
 
     /*
-     * collide()
+     * get_element()
      * 
-     * @Brief: Check if entity collide with mapelement
+     * @Brief: Get element at map coords
      */
-    pub fn collide<T: WithPosition + WithSize + ?Sized>(&self, type_collide: MapElementCollideType, entity: &T) -> bool {
+    pub fn get_element(&self, coord: &MapCoord) -> Option<&MapElement> {
+        if coord.lig < 0 || coord.col < 0 {
+            return None;
+        }
+        self.level.get(coord.lig as usize)
+            .and_then(|line| line.get(coord.col as usize))
+            .and_then(|id_element| self.elements.get(id_element))
+    }
 
+    /*
+     * get_tiles_under()
+     * 
+     * @Brief: Get elements overlapped by the entity
+     */
+    pub fn get_tiles_under<T: WithPosition + WithSize + ?Sized>(&self, entity: &T) -> Vec<&MapElement> {
         let entity_size = entity.get_size();
         let entity_position = entity.get_position();
 
-        let height: Size = if entity_size.h > MAP_TILE_SIZE as Size { MAP_TILE_SIZE as Size } else { entity_size.h };
-        let width: Size = if entity_size.w > MAP_TILE_SIZE as Size { MAP_TILE_SIZE as Size } else { entity_size.w };
-
-
-        let (position1, position2) =
-            match type_collide {
-                MapElementCollideType::Above => {
-                    (
-                        Position2d {x: entity_position.x + 1., y: entity_position.y - 1. },
-                        Position2d {x: entity_position.x + width as Position - 2.,y: entity_position.y - 1. }
-                    )
-                },
-                MapElementCollideType::Below => {
-                    (
-                        Position2d {x: entity_position.x + 1., y: entity_position.y + width as Position },
-                        Position2d {x: entity_position.x + width as Position - 2.,y: entity_position.y + width as Position }
-                    )
-                },
-                MapElementCollideType::Left => {
-                    (
-                        Position2d {x: entity_position.x - 1., y: entity_position.y + 3. },
-                        Position2d {x: entity_position.x - 1. ,y: entity_position.y + height as Position - 2.}
-                    )
-                },
-                MapElementCollideType::Right => {
-                    (
-                        Position2d {x: entity_position.x + width as Position, y: entity_position.y + 3. },
-                        Position2d {x: entity_position.x + width as Position ,y: entity_position.y + height as Position - 2.}
-                    )
+        let first = MapCoord::from(*entity_position);
+        let last = MapCoord::from(Position2d {
+            x: entity_position.x + entity_size.w as Position - 1.,
+            y: entity_position.y + entity_size.h as Position - 1.,
+        });
+
+        let mut elements = Vec::new();
+        for lig in first.lig..=last.lig {
+            for col in first.col..=last.col {
+                if let Some(element) = self.get_element(&MapCoord { lig, col }) {
+                    elements.push(element);
+                }
+            }
+        }
+
+        elements
+    }
+
+    /*
+     * activate_checkpoint()
+     * 
+     * @Brief: Activate the checkpoint overlapped by the entity (if not already the active one),
+     *         keeping a copy of the level to restore at the respawn
+     */
+    pub fn activate_checkpoint<T: WithPosition + WithSize + ?Sized>(&mut self, entity: &T) -> bool {
+        let aabb = Aabb::from_entity(entity);
+        let (lig_first, lig_last) = Map::tiles_range(aabb.top(), aabb.bottom());
+        let (col_first, col_last) = Map::tiles_range(aabb.left(), aabb.right());
+
+        for lig in lig_first..=lig_last {
+            for col in col_first..=col_last {
+                let coord = MapCoord { lig, col };
+                let is_checkpoint = self.get_element(&coord).map(|element| element.checkpoint).unwrap_or(false);
+                let is_active = self.checkpoint.as_ref().map(|checkpoint| checkpoint.coord == coord).unwrap_or(false);
+
+                if is_checkpoint && !is_active {
+                    self.checkpoint = Some(MapCheckpoint { coord, level: self.level.clone() });
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /*
+     * take_power_up()
+     * 
+     * @Brief: Pick up the power-up overlapped by the entity (it is removed from the level)
+     */
+    pub fn take_power_up<T: WithPosition + WithSize + ?Sized>(&mut self, entity: &T) -> Option<MapPowerUp> {
+        let aabb = Aabb::from_entity(entity);
+        let (lig_first, lig_last) = Map::tiles_range(aabb.top(), aabb.bottom());
+        let (col_first, col_last) = Map::tiles_range(aabb.left(), aabb.right());
+
+        for lig in lig_first..=lig_last {
+            for col in col_first..=col_last {
+                let power_up = self.get_element(&MapCoord { lig, col }).and_then(|element| element.power_up);
+
+                if power_up.is_some() {
+                    self.level[lig as usize][col as usize] = MAP_EMPTY;
+                    return power_up;
+                }
+            }
+        }
+
+        None
+    }
+
+    /*
+     * get_respawn_position()
+     * 
+     * @Brief: Position of the active checkpoint, or the start of the player
+     */
+    pub fn get_respawn_position(&self) -> Option<Position2d> {
+        match &self.checkpoint {
+            Some(checkpoint) => Some(MapCoord::to_position2d(checkpoint.coord)),
+            None => self.player_start,
+        }
+    }
+
+    /*
+     * restore_checkpoint()
+     * 
+     * @Brief: Put back the level as it was when the active checkpoint was touched (or when it was loaded)
+     */
+    pub fn restore_checkpoint(&mut self) {
+        match &self.checkpoint {
+            Some(checkpoint) => self.level = checkpoint.level.clone(),
+            None => self.level = self.start_level.clone(),
+        }
+    }
+
+    /*
+     * get_liquid_at()
+     * 
+     * @Brief: Liquid of the tile at pixel
+     */
+    pub fn get_liquid_at(&self, x: Position, y: Position) -> Option<MapLiquid> {
+        self.get_tile_at(x, y).and_then(|element| element.liquid)
+    }
+
+    /*
+     * get_liquid_surface()
+     * 
+     * @Brief: Top of the liquid at pixel (going up the column while the tiles are liquid)
+     */
+    pub fn get_liquid_surface(&self, x: Position, y: Position) -> Option<Position> {
+        self.get_liquid_at(x, y)?;
+
+        let mut top = (y / MAP_TILE_SIZE).floor() * MAP_TILE_SIZE;
+        while top > 0. && self.get_liquid_at(x, top - 1.).is_some() {
+            top -= MAP_TILE_SIZE;
+        }
+        Some(top)
+    }
+
+    /*
+     * get_surface()
+     * 
+     * @Brief: Surface of the tiles under the bottom side of the box (the one under its middle first,
+     *         then the closest to it : standing on the edge of a tile still uses its surface)
+     */
+    pub fn get_surface(&self, aabb: &Aabb) -> Option<MapSurface> {
+        let middle = ((aabb.x + aabb.w / 2.) / MAP_TILE_SIZE).floor() as isize;
+        let mut below = Map::tiles_along(MapElementCollideType::Below, aabb);
+        below.sort_by_key(|coord| (coord.col - middle).abs());
+
+        below.iter().find_map(|coord| self.get_element(coord).and_then(|element| element.surface))
+    }
+
+    /*
+     * get_hazard()
+     * 
+     * @Brief: Worst hazard among the elements overlapped by the entity, with the center of its tile
+     */
+    pub fn get_hazard<T: WithPosition + WithSize + ?Sized>(&self, entity: &T) -> Option<(MapHazard, Position2d)> {
+        let aabb = Aabb::from_entity(entity);
+        let (lig_first, lig_last) = Map::tiles_range(aabb.top(), aabb.bottom());
+        let (col_first, col_last) = Map::tiles_range(aabb.left(), aabb.right());
+
+        let mut hazards = Vec::new();
+        for lig in lig_first..=lig_last {
+            for col in col_first..=col_last {
+                let coord = MapCoord { lig, col };
+                if let Some(hazard) = self.get_element(&coord).and_then(|element| element.hazard) {
+                    let corner = MapCoord::to_position2d(coord);
+                    hazards.push((hazard, Position2d { x: corner.x + MAP_TILE_SIZE / 2., y: corner.y + MAP_TILE_SIZE / 2. }));
                 }
+            }
+        }
+
+        hazards.into_iter().max_by_key(|(hazard, _)| match hazard {
+            MapHazard::Hurt(damage) => *damage,
+            MapHazard::Kill => u32::MAX,
+        })
+    }
+
+    /*
+     * tiles_along()
+     * 
+     * @Brief: Tiles touching one side of a box (from one end of the side to the other, whatever the size)
+     */
+    pub fn tiles_along(type_collide: MapElementCollideType, aabb: &Aabb) -> Vec<MapCoord> {
+        let (lig_first, lig_last) = Map::tiles_range(aabb.top(), aabb.bottom());
+        let (col_first, col_last) = Map::tiles_range(aabb.left(), aabb.right());
+
+        match type_collide {
+            MapElementCollideType::Above => {
+                let lig = ((aabb.top() - 1.) / MAP_TILE_SIZE).floor() as isize;
+                (col_first..=col_last).map(|col| MapCoord { lig, col }).collect()
+            },
+            MapElementCollideType::Below => {
+                let lig = (aabb.bottom() / MAP_TILE_SIZE).floor() as isize;
+                (col_first..=col_last).map(|col| MapCoord { lig, col }).collect()
+            },
+            MapElementCollideType::Left => {
+                let col = ((aabb.left() - 1.) / MAP_TILE_SIZE).floor() as isize;
+                (lig_first..=lig_last).map(|lig| MapCoord { lig, col }).collect()
+            },
+            MapElementCollideType::Right => {
+                let col = (aabb.right() / MAP_TILE_SIZE).floor() as isize;
+                (lig_first..=lig_last).map(|lig| MapCoord { lig, col }).collect()
+            },
+        }
+    }
+
+    /*
+     * is_solid()
+     * 
+     * @Brief: Check if the tile at map coords blocks entities
+     */
+    pub fn is_solid(&self, coord: &MapCoord) -> bool {
+        self.get_element(coord).map(|element| element.solid).unwrap_or(false)
+    }
+
+    /*
+     * is_blocking()
+     * 
+     * @Brief: Check if the tile blocks an entity on one side (one-way platforms only block from above)
+     */
+    pub fn is_blocking(&self, coord: &MapCoord, type_collide: MapElementCollideType) -> bool {
+        match self.get_element(coord) {
+            Some(element) => element.solid || (element.one_way && type_collide == MapElementCollideType::Below),
+            None => false,
+        }
+    }
+
+    /*
+     * has_room_above()
+     * 
+     * @Brief: Check no tile blocks the box if it grows up by height pixels (to stand up)
+     */
+    pub fn has_room_above(&self, aabb: &Aabb, height: Position) -> bool {
+        let (lig_first, lig_last) = Map::tiles_range(aabb.top() - height, aabb.top());
+        let (col_first, col_last) = Map::tiles_range(aabb.left(), aabb.right());
+
+        !(lig_first..=lig_last).any(|lig| {
+            (col_first..=col_last).any(|col| self.is_blocking(&MapCoord { lig, col }, MapElementCollideType::Above))
+        })
+    }
+
+    /*
+     * get_ledge()
+     * 
+     * @Brief: Corner of a ledge just above the top of the box, on one side (Left / Right) :
+     *         a solid tile against the box with an empty tile above it (room to climb up)
+     */
+    pub fn get_ledge(&self, aabb: &Aabb, side: MapElementCollideType) -> Option<Position2d> {
+        let col = match side {
+            MapElementCollideType::Left => ((aabb.left() - 1.) / MAP_TILE_SIZE).floor() as isize,
+            MapElementCollideType::Right => (aabb.right() / MAP_TILE_SIZE).floor() as isize,
+            _ => return None,
+        };
+        let lig = (aabb.top() / MAP_TILE_SIZE).floor() as isize;
+        let corner = MapCoord::to_position2d(MapCoord { lig, col });
+
+        if aabb.top() - corner.y > MAP_LEDGE_REACH
+            || !self.is_solid(&MapCoord { lig, col })
+            || self.is_blocking(&MapCoord { lig: lig - 1, col }, MapElementCollideType::Below) {
+            return None;
+        }
+
+        match side {
+            MapElementCollideType::Left => Some(Position2d { x: corner.x + MAP_TILE_SIZE, y: corner.y }),
+            _ => Some(corner),
+        }
+    }
+
+    /*
+     * is_on_one_way()
+     * 
+     * @Brief: Check if the box stands only on one-way platforms (so it can drop through)
+     */
+    pub fn is_on_one_way(&self, aabb: &Aabb) -> bool {
+        let below = Map::tiles_along(MapElementCollideType::Below, aabb);
+
+        !below.iter().any(|coord| self.is_solid(coord))
+            && below.iter().any(|coord| self.is_blocking(coord, MapElementCollideType::Below))
+    }
+
+    /*
+     * is_on_ladder()
+     * 
+     * @Brief: Check if the middle of the box (from its top to its bottom) is on a climbable tile
+     */
+    pub fn is_on_ladder(&self, aabb: &Aabb) -> bool {
+        let col = ((aabb.left() + aabb.w / 2.) / MAP_TILE_SIZE).floor() as isize;
+        let (lig_first, lig_last) = Map::tiles_range(aabb.top(), aabb.bottom());
+
+        (lig_first..=lig_last).any(|lig| {
+            self.get_element(&MapCoord { lig, col }).map(|element| element.climbable).unwrap_or(false)
+        })
+    }
+
+    /*
+     * sweep()
+     * 
+     * @Brief: Move a box against the solid tiles (x then y), every tile crossed is checked
+     *         so a fast entity never goes through a wall, whatever its size
+     *         One-way platforms stop a box falling on them, unless it drops through
+     *         Slopes move the box along their ground (checked at the middle of its bottom side)
+     */
+    pub fn sweep(&self, aabb: &Aabb, movement: &Velocity2d, options: &SweepOptions) -> SweepResult {
+        let mut result = SweepResult { aabb: *aabb, contacts: Vec::new() };
+        // On a slope, the side of the box is up to half its width (45°) above its middle
+        let step = if options.grounded && self.get_slope_ground(aabb).is_some() {
+            (aabb.w / 2. + 1.).clamp(MAP_SLOPE_STEP, MAP_TILE_SIZE - 1.)
+        } else {
+            0.
+        };
+
+        self.push_out(&mut result);
+        self.sweep_x(&mut result, movement.vx, step);
+        if step > 0. {
+            self.step_up(&mut result, step);
+        }
+        self.sweep_y(&mut result, movement.vy, options.drop_through);
+        self.resolve_slope(&mut result, aabb, movement.vx, movement.vy, options.grounded && movement.vy >= 0.);
+
+        result
+    }
+
+    /*
+     * push_out()
+     * 
+     * @Brief: Move the box outside of the solid tiles it is already inside (shortest way)
+     */
+    fn push_out(&self, result: &mut SweepResult) {
+        for _ in 0..MAP_PUSH_OUT_ITERATIONS {
+            let aabb = result.aabb;
+            let (lig_first, lig_last) = Map::tiles_range(aabb.top(), aabb.bottom());
+            let (col_first, col_last) = Map::tiles_range(aabb.left(), aabb.right());
+
+            let inside = (lig_first..=lig_last)
+                .flat_map(|lig| (col_first..=col_last).map(move |col| MapCoord { lig, col }))
+                .find(|coord| self.is_solid(coord));
+
+            let Some(coord) = inside else { return };
+            let tile = MapCoord::to_position2d(coord);
+
+            // Depth on each side of the tile
+            let left = aabb.right() - tile.x;
+            let right = tile.x + MAP_TILE_SIZE - aabb.left();
+            let up = aabb.bottom() - tile.y;
+            let down = tile.y + MAP_TILE_SIZE - aabb.top();
+            let penetration = left.min(right).min(up).min(down);
+
+            let normal = if penetration == up {
+                result.aabb.y -= up;
+                Position2d { x: 0., y: -1. }
+            } else if penetration == down {
+                result.aabb.y += down;
+                Position2d { x: 0., y: 1. }
+            } else if penetration == left {
+                result.aabb.x -= left;
+                Position2d { x: -1., y: 0. }
+            } else {
+                result.aabb.x += right;
+                Position2d { x: 1., y: 0. }
             };
+            result.contacts.push(Contact { normal, time: 0., penetration });
+        }
+    }
+
+    fn sweep_x(&self, result: &mut SweepResult, dx: Position, step: Position) {
+        let aabb = result.aabb;
+        // The bottom of the box can go up a step
+        let (lig_first, lig_last) = Map::tiles_range(aabb.top() - step, aabb.bottom() - step);
+        let blocked = |col: isize| (lig_first..=lig_last).any(|lig| self.is_solid(&MapCoord { lig, col }));
+
+        if dx > 0. {
+            let col_first = (aabb.right() / MAP_TILE_SIZE).ceil() as isize;
+            let col_last = ((aabb.right() + dx) / MAP_TILE_SIZE).ceil() as isize - 1;
+            if let Some(col) = (col_first..=col_last).find(|col| blocked(*col)) {
+                let wall = col as Position * MAP_TILE_SIZE;
+                result.aabb.x = wall - aabb.w;
+                result.contacts.push(Contact { normal: Position2d { x: -1., y: 0. }, time: (wall - aabb.right()) / dx, penetration: 0. });
+                return;
+            }
+        } else if dx < 0. {
+            let col_first = (aabb.left() / MAP_TILE_SIZE).floor() as isize - 1;
+            let col_last = ((aabb.left() + dx) / MAP_TILE_SIZE).floor() as isize;
+            if let Some(col) = (col_last..=col_first).rev().find(|col| blocked(*col)) {
+                let wall = (col + 1) as Position * MAP_TILE_SIZE;
+                result.aabb.x = wall;
+                result.contacts.push(Contact { normal: Position2d { x: 1., y: 0. }, time: (wall - aabb.left()) / dx, penetration: 0. });
+                return;
+            }
+        }
+        result.aabb.x += dx;
+    }
+
+    fn sweep_y(&self, result: &mut SweepResult, dy: Position, drop_through: bool) {
+        let aabb = result.aabb;
+        let (col_first, col_last) = Map::tiles_range(aabb.left(), aabb.right());
+        let blocked = |lig: isize| (col_first..=col_last).any(|col| self.is_solid(&MapCoord { lig, col }));
+        // Rows below the box : it was above the one-way platforms found there
+        let landing = |lig: isize| (col_first..=col_last).any(|col| self.is_blocking(&MapCoord { lig, col }, MapElementCollideType::Below));
+
+        if dy > 0. {
+            let lig_first = (aabb.bottom() / MAP_TILE_SIZE).ceil() as isize;
+            let lig_last = ((aabb.bottom() + dy) / MAP_TILE_SIZE).ceil() as isize - 1;
+            let found = if drop_through { (lig_first..=lig_last).find(|lig| blocked(*lig)) } else { (lig_first..=lig_last).find(|lig| landing(*lig)) };
+            if let Some(lig) = found {
+                let ground = lig as Position * MAP_TILE_SIZE;
+                result.aabb.y = ground - aabb.h;
+                result.contacts.push(Contact { normal: Position2d { x: 0., y: -1. }, time: (ground - aabb.bottom()) / dy, penetration: 0. });
+                return;
+            }
+        } else if dy < 0. {
+            let lig_first = (aabb.top() / MAP_TILE_SIZE).floor() as isize - 1;
+            let lig_last = ((aabb.top() + dy) / MAP_TILE_SIZE).floor() as isize;
+            if let Some(lig) = (lig_last..=lig_first).rev().find(|lig| blocked(*lig)) {
+                let ceiling = (lig + 1) as Position * MAP_TILE_SIZE;
+                result.aabb.y = ceiling;
+                result.contacts.push(Contact { normal: Position2d { x: 0., y: 1. }, time: (ceiling - aabb.top()) / dy, penetration: 0. });
+                return;
+            }
+        }
+        result.aabb.y += dy;
+    }
+
+    /*
+     * step_up()
+     * 
+     * @Brief: Put the box on the solid tiles at the bottom of its side (top of a slope)
+     */
+    fn step_up(&self, result: &mut SweepResult, step: Position) {
+        let aabb = result.aabb;
+        let (lig_first, lig_last) = Map::tiles_range(aabb.bottom() - step, aabb.bottom());
+        let (col_first, col_last) = Map::tiles_range(aabb.left(), aabb.right());
+
+        let top = (lig_first..=lig_last)
+            .flat_map(|lig| (col_first..=col_last).map(move |col| MapCoord { lig, col }))
+            .filter(|coord| self.is_solid(coord))
+            .map(|coord| coord.lig as Position * MAP_TILE_SIZE)
+            .reduce(Position::min);
+
+        if let Some(top) = top.filter(|top| *top < aabb.bottom()) {
+            result.aabb.y = top - aabb.h;
+            result.contacts.push(Contact { normal: Position2d { x: 0., y: -1. }, time: 0., penetration: aabb.bottom() - top });
+        }
+    }
+
+    /*
+     * get_slope_ground()
+     * 
+     * @Brief: Ground (y) and normal of the slope under the middle of the bottom side of the box
+     */
+    pub fn get_slope_ground(&self, aabb: &Aabb) -> Option<(Position, Position2d)> {
+        let x = aabb.x + aabb.w / 2.;
+        let col = (x / MAP_TILE_SIZE).floor() as isize;
+        let lig_feet = ((aabb.bottom() - 1.) / MAP_TILE_SIZE).floor() as isize;
 
-        let id1 = self.get_tile_at(position1.x, position1.y);
-        let id2 = self.get_tile_at(position2.x, position2.y);
-        if let Some(id) = id1 {
-            if id.solid { return true }
+        // Tile of the feet, or the one below
+        for lig in lig_feet..=lig_feet + 1 {
+            let Some(element) = self.get_element(&MapCoord { lig, col }) else { continue };
+            let Some([left, right]) = element.slope else { continue };
+            let Some(height) = element.get_slope_height(x - col as Position * MAP_TILE_SIZE) else { continue };
+
+            let ground = (lig + 1) as Position * MAP_TILE_SIZE - height;
+            let length = (1. + (right - left) * (right - left)).sqrt();
+            return Some((ground, Position2d { x: (left - right) / length, y: -1. / length }));
         }
-        if let Some(id) = id2 {
-            if id.solid { return true }
+
+        None
+    }
+
+    /*
+     * resolve_slope()
+     * 
+     * @Brief: Put the box on the ground of a slope when it went inside (or just left it, when sticking :
+     *         the ground under its middle is lower than the one it stood on by up to half its width plus its move,
+     *         times the gradient of the slope), or on the ground at the bottom of the slope it just left
+     */
+    fn resolve_slope(&self, result: &mut SweepResult, start: &Aabb, dx: Position, dy: Position, stick: bool) {
+        let aabb = result.aabb;
+        let supported = result.contacts.iter().any(|contact| contact.is_ground());
+        let Some((ground, normal)) = self.get_slope_ground(&aabb) else {
+            if let Some((_, normal)) = self.get_slope_ground(start).filter(|_| stick && !supported) {
+                self.stick_to_ground(result, dx.abs() * (normal.x / normal.y).abs());
+            }
+            return;
+        };
+
+        let depth = aabb.bottom() - ground;
+
+        // Inside the slope (by its move, or a small step) / above it, going down
+        let inside = depth > 0. && depth - dy.max(0.) <= MAP_SLOPE_STEP;
+        let gradient = (normal.x / normal.y).abs();
+        let sticking = stick && !supported && depth <= 0. && -depth <= (aabb.w / 2. + dx.abs()) * gradient;
+
+        if inside || sticking {
+            result.aabb.y = ground - aabb.h;
+            result.contacts.push(Contact { normal, time: 0., penetration: depth.max(0.) });
         }
-        return false
+    }
+
+    /*
+     * stick_to_ground()
+     * 
+     * @Brief: Put the box on the solid (or one-way) tiles below it, when they are up to a distance under it
+     */
+    fn stick_to_ground(&self, result: &mut SweepResult, distance: Position) {
+        let aabb = result.aabb;
+        let lig = (aabb.bottom() / MAP_TILE_SIZE).ceil() as isize;
+        let ground = lig as Position * MAP_TILE_SIZE;
+        let (col_first, col_last) = Map::tiles_range(aabb.left(), aabb.right());
+
+        if ground - aabb.bottom() <= distance
+            && (col_first..=col_last).any(|col| self.is_blocking(&MapCoord { lig, col }, MapElementCollideType::Below)) {
+            result.aabb.y = ground - aabb.h;
+            result.contacts.push(Contact { normal: Position2d { x: 0., y: -1. }, time: 0., penetration: 0. });
+        }
+    }
+
+    /*
+     * tiles_range()
+     * 
+     * @Brief: First and last tiles (included) covered by [start, end[ on one axis
+     */
+    fn tiles_range(start: Position, end: Position) -> (isize, isize) {
+        ((start / MAP_TILE_SIZE).floor() as isize, (end / MAP_TILE_SIZE).ceil() as isize - 1)
     }
 
 }
 
 impl Drawable for Map {
-    fn draw(&mut self, graphics: &mut game2d::graphics::graphics::Graphics) {
-
-        for (pos_l, l) in self.level.iter().enumerate()
-        {
-            for (pos_c, c) in l.iter().enumerate() {
-                let mut image = Err(format!("Informations {} not found", c));
-                
-                // Search information of element
-                if let Some(element) = self.elements.get(c) {
-                    image = graphics.new_image(&element.filename);
-                } 
-                
-                // Draw image
-                if let Ok(image) = image {
-                    let scalex = (MAP_TILE_SIZE as Transformation / image.get_width() as Transformation) as Transformation;
-                    let scaley = (MAP_TILE_SIZE as Transformation / image.get_height() as Transformation) as Transformation;
-                    graphics.draw_full(
-                        &image, 
-                        (pos_c as Position * MAP_TILE_SIZE as Position), 
-                        (pos_l as Position * MAP_TILE_SIZE as Position), 
-                        0., scalex, scaley, 0., 0. 
-                    );
-                }
+    fn draw(&mut self, graphics: &mut Graphics) {
+        self.draw_with_camera(graphics, &Camera::default());
+    }
+}
+
+impl CameraDrawable for Map {
+    fn draw_with_camera(&mut self, graphics: &mut Graphics, camera: &Camera) {
+
+        // Only the tiles seen by the camera
+        for (coord, c) in self.get_visible_tiles(camera) {
+            // Draw image (loaded by load_level), the active checkpoint has its own
+            let active = self.checkpoint.as_ref().map(|checkpoint| checkpoint.coord == coord).unwrap_or(false);
+            let image = if active { self.active_textures.get(&c).or(self.textures.get(&c)) } else { self.textures.get(&c) };
+            if let Some(image) = image {
+                let scalex = (MAP_TILE_SIZE as Transformation / image.get_width() as Transformation) as Transformation;
+                let scaley = (MAP_TILE_SIZE as Transformation / image.get_height() as Transformation) as Transformation;
+                let position = camera.to_screen(MapCoord::to_position2d(coord));
+                graphics.draw_full(
+                    image, 
+                    position.x, 
+                    position.y, 
+                    0., scalex, scaley, 0., 0. 
+                );
             }
         }        
     }
 }
 
-// ################################################################################################################
-// #                                             L E V E L                                                        #
-// ################################################################################################################
-fn level_1() -> MapLevel {
-    let level = 
-        vec![
-            vec!['1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1'],
-            vec!['1', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '1'],
-            vec!['1', '0', '0', '0', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '0', '0', '0', '1'],
-            vec!['1', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '1', '1'],
-            vec!['1', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '1'],
-            vec!['1', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '1', '1', '1'],
-            vec!['1', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '1'],
-            vec!['1', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '1', '1', '1', '1', '1', '1', '1', '1'],
-            vec!['1', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '1'],
-            vec!['1', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '1'],
-            vec!['1', '0', '0', '0', '0', '0', '0', '0', '0', '0', '1', '1', '1', '1', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '1'],
-            vec!['1', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '1'],
-            vec!['1', '1', '1', '1', '1', '1', '1', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '1'],
-            vec!['1', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '1'],
-            vec!['1', '1', '0', '0', '0', '0', '0', '0', '0', '0', '1', '1', '1', '1', '0', '0', '0', '0', '0', '0', '0', '0', '0', '0', '1'],
-            vec!['1', '0', '0', '0', '0', '0', '0', '0', '0', '1', '0', '0', '0', '0', '1', '0', '0', '0', '0', '0', '0', '0', '0', '0', '1'],
-            vec!['1', '0', '0', '0', '0', '0', '0', '0', '1', '0', '0', '0', '0', '0', '0', '1', '0', '0', '0', '0', '0', '0', '0', '0', '1'],
-            vec!['1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1', '1'],
-        ];
-    
-        level
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Body;
+
+    // Map with a wall, a one-way platform, 45° and 22.5° slopes and a ladder (no image needed)
+    fn map() -> Map {
+        let mut map = Map::new();
+        map.tileset.insert('1', MapElement { name: "Wall".to_string(), solid: true, ..Default::default() });
+        map.tileset.insert('-', MapElement { name: "Platform".to_string(), one_way: true, ..Default::default() });
+        for (id, slope) in [('/', [0., 1.]), ('\\', [1., 0.]), ('a', [0., 0.5]), ('b', [0.5, 1.]), ('c', [1., 0.5]), ('d', [0.5, 0.])] {
+            map.tileset.insert(id, MapElement { name: format!("Slope {}", id), slope: Some(slope), ..Default::default() });
+        }
+        map.tileset.insert('H', MapElement { name: "Ladder".to_string(), climbable: true, ..Default::default() });
+        map.elements = map.tileset.clone();
+        map
+    }
+
+    // Map with a level given row by row (no start needed)
+    fn map_with(rows: &[&str]) -> Map {
+        let mut map = map();
+        map.level = rows.iter().map(|row| row.chars().collect()).collect();
+        map
+    }
+
+    fn sweep(map: &Map, aabb: Aabb, vx: Position, vy: Position, drop_through: bool) -> SweepResult {
+        map.sweep(&aabb, &Velocity2d { vx, vy }, &SweepOptions { drop_through, grounded: false })
+    }
+
+    // Box of 32 x 32 px walking on the ground (with the gravity of the sprites, at 120 steps / second)
+    // from x for a time : its box at the end, and the number of steps it was not on the ground
+    fn walk(map: &Map, x: Position, bottom: Position, vx: Position, time: f32) -> (Aabb, usize) {
+        let dt = 1. / 120.;
+        let gravity = 1000.;
+        let mut aabb = Aabb { x, y: bottom - 32., w: 32., h: 32. };
+        let mut vy = 0.;
+        let mut grounded = true;
+        let mut airborne = 0;
+        for _ in 0..(time / dt) as usize {
+            let movement = Velocity2d { vx: vx * dt, vy: vy * dt + 0.5 * gravity * dt * dt };
+            let result = map.sweep(&aabb, &movement, &SweepOptions { drop_through: false, grounded });
+            aabb = result.aabb;
+            vy += gravity * dt;
+            grounded = result.contacts.iter().any(|contact| contact.is_ground());
+            if grounded {
+                vy = 0.;
+            } else {
+                airborne += 1;
+            }
+        }
+        (aabb, airborne)
+    }
+
+    fn normals(result: &SweepResult) -> Vec<(Position, Position)> {
+        result.contacts.iter().map(|contact| (contact.normal.x, contact.normal.y)).collect()
+    }
+
+    #[test]
+    fn parse_level_reads_rows_and_start() {
+        let (level, start) = map().parse_level("1111\n1P01\n\n1-01\n1111\n").unwrap();
+
+        assert_eq!(level.len(), 4);
+        assert_eq!(level[1], vec!['1', MAP_EMPTY, MAP_EMPTY, '1']);
+        assert_eq!(level[2], vec!['1', '-', MAP_EMPTY, '1']);
+        assert_eq!(start, Position2d { x: MAP_TILE_SIZE, y: MAP_TILE_SIZE });
+    }
+
+    #[test]
+    fn parse_level_rejects_unknown_element() {
+        let error = map().parse_level("1111\n1Px1\n1111").unwrap_err();
+        assert_eq!(error, "unknown element 'x' at line 2, column 3");
+    }
+
+    #[test]
+    fn parse_level_rejects_missing_or_second_start() {
+        assert_eq!(map().parse_level("1111\n1001\n1111").unwrap_err(), "no start 'P'");
+        assert!(map().parse_level("1111\n1PP1\n1111").unwrap_err().starts_with("second start 'P' at line 2, column 3"));
+    }
+
+    #[test]
+    fn parse_level_rejects_ragged_or_empty_level() {
+        assert_eq!(map().parse_level("1111\n1P1\n1111").unwrap_err(), "line 2 has 3 columns, expected 4");
+        assert_eq!(map().parse_level("\n\n").unwrap_err(), "level is empty");
+    }
+
+    #[test]
+    fn level_elements_can_not_replace_the_tileset() {
+        let map = map();
+        let tiled = |id: char| HashMap::from([(id, MapElement { name: "tiled:0".to_string(), ..Default::default() })]);
+
+        let elements = map.with_level_elements(tiled('\u{E000}')).unwrap();
+        assert_eq!(elements.len(), map.tileset.len() + 1);
+        assert_eq!(map.with_level_elements(tiled('1')).unwrap_err(), "id '1' of tiled:0 already used by Wall of the tileset");
+
+        // Nothing kept from a level to the next
+        assert_eq!(map.with_level_elements(HashMap::new()).unwrap().len(), map.tileset.len());
+    }
+
+    #[test]
+    fn each_texture_is_loaded_once() {
+        let mut map = map_with(&[
+            "1111",
+            "1HH1",
+            "-/-1",
+        ]);
+        map.elements.insert('C', MapElement { name: "Checkpoint".to_string(), filename: "checkpoint.png".to_string(),
+                                              active_filename: Some("checkpoint_active.png".to_string()), checkpoint: true, ..Default::default() });
+        map.level[1][1] = 'C';
+
+        let mut loaded = Vec::new();
+        let (textures, active_textures) = Map::load_textures(&map.elements, &map.level, |filename| {
+            loaded.push(filename.to_string());
+            Ok::<_, String>(filename.len())
+        }).unwrap();
+        assert_eq!(loaded.len(), 6);
+        assert_eq!(textures.len(), 5);
+        assert_eq!(active_textures.len(), 1);
+
+        let error = Map::load_textures(&map.elements, &map.level, |_| Err::<(), _>("not found")).unwrap_err();
+        assert_eq!(error, "unable to load  (not found)");
+    }
+
+    // cargo test -- --ignored --nocapture : time of the draw path (culling and texture of each tile)
+    #[test]
+    #[ignore]
+    fn draw_path_of_a_200x200_map() {
+        let mut map = map();
+        map.level = vec![vec!['1'; 200]; 200];
+        let (textures, _) = Map::load_textures(&map.elements, &map.level, |filename| Ok::<_, String>(filename.to_string())).unwrap();
+
+        let mut camera = Camera::new(800, 600);
+        camera.set_bounds(Some(map.get_pixel_size()));
+        let frames = 1000;
+        let start = std::time::Instant::now();
+        let mut drawn = 0;
+        for frame in 0..frames {
+            camera.follow(&Position2d { x: (frame * 6) as Position, y: (frame * 6) as Position }, &Size2d { w: 32, h: 32 }, &(1. / 60.));
+            drawn += map.get_visible_tiles(&camera).filter(|(_, c)| textures.contains_key(c)).count();
+        }
+        let frame_time = start.elapsed() / frames;
+
+        println!("{} tiles drawn per frame out of 40000, {:?} per frame", drawn / frames as usize, frame_time);
+        assert!(drawn / (frames as usize) <= 26 * 20);
+        assert!(frame_time.as_micros() < 1000);
+    }
+
+    #[test]
+    fn only_the_tiles_seen_by_the_camera_are_drawn() {
+        let mut map = map();
+        map.level = vec![vec!['1'; 200]; 200];
+
+        let mut camera = Camera::new(800, 600);
+        camera.set_dead_zone(Size2d { w: 0, h: 0 });
+        camera.set_smoothing(0.);
+        camera.set_bounds(Some(map.get_pixel_size()));
+
+        // Camera not aligned on the tiles : 26 x 20 tiles out of 40000, all of them (partly) on the screen
+        camera.follow(&Position2d { x: 3210., y: 4105. }, &Size2d { w: 32, h: 32 }, &0.);
+        let tiles: Vec<(MapCoord, char)> = map.get_visible_tiles(&camera).collect();
+        assert_eq!(tiles.len(), 26 * 20);
+        let view = camera.get_position();
+        for (coord, _) in tiles.iter() {
+            let tile = MapCoord::to_position2d(*coord);
+            assert!(tile.x + MAP_TILE_SIZE > view.x && tile.x < view.x + 800.);
+            assert!(tile.y + MAP_TILE_SIZE > view.y && tile.y < view.y + 600.);
+        }
+
+        // Corners of the map : nothing outside of it
+        camera.follow(&Position2d { x: 0., y: 0. }, &Size2d { w: 32, h: 32 }, &0.);
+        assert_eq!(map.get_visible_tiles(&camera).count(), 25 * 19);
+        camera.follow(&Position2d { x: 6400., y: 6400. }, &Size2d { w: 32, h: 32 }, &0.);
+        assert_eq!(map.get_visible_tiles(&camera).last().map(|(coord, _)| coord), Some(MapCoord { lig: 199, col: 199 }));
+    }
+
+    #[test]
+    fn sweep_stops_against_walls_floors_and_ceilings() {
+        let map = map_with(&[
+            "1111",
+            "0001",
+            "0001",
+            "1111",
+        ]);
+
+        // Right, into the wall (half of the move done)
+        let result = sweep(&map, Aabb { x: 48., y: 40., w: 16., h: 16. }, 64., 0., false);
+        assert_eq!(normals(&result), vec![(-1., 0.)]);
+        assert_eq!(result.aabb.x, 80.);
+        assert_eq!(result.contacts[0].time, 0.5);
+
+        // Down, on the floor / up, under the ceiling
+        let result = sweep(&map, Aabb { x: 8., y: 40., w: 16., h: 16. }, 0., 100., false);
+        assert_eq!(normals(&result), vec![(0., -1.)]);
+        assert_eq!(result.aabb.y, 80.);
+        let result = sweep(&map, Aabb { x: 8., y: 40., w: 16., h: 16. }, 0., -100., false);
+        assert_eq!(normals(&result), vec![(0., 1.)]);
+        assert_eq!(result.aabb.y, 32.);
+
+        // Fast move : the wall is not crossed
+        let result = sweep(&map, Aabb { x: 0., y: 40., w: 16., h: 16. }, 1000., 0., false);
+        assert_eq!(result.aabb.x, 80.);
+    }
+
+    #[test]
+    fn sweep_at_the_corners() {
+        let map = map_with(&[
+            "0000",
+            "0000",
+            "0010",
+            "1111",
+        ]);
+
+        // Diagonal move on the top left corner of a block : lands on it
+        let result = sweep(&map, Aabb { x: 24., y: 24., w: 16., h: 16. }, 30., 30., false);
+        assert_eq!(normals(&result), vec![(0., -1.)]);
+        assert_eq!(result.aabb.get_position(), Position2d { x: 54., y: 48. });
+
+        // Passing just beside the corner (edges touching) : no contact
+        let result = sweep(&map, Aabb { x: 48., y: 0., w: 16., h: 16. }, 0., 40., false);
+        assert!(result.contacts.is_empty());
+        assert_eq!(result.aabb.y, 40.);
+
+        // Along a floor made of several tiles : no wall at the seams
+        let result = sweep(&map, Aabb { x: 0., y: 80., w: 16., h: 16. }, 30., 1., false);
+        assert_eq!(normals(&result), vec![(0., -1.)]);
+        assert_eq!(result.aabb.get_position(), Position2d { x: 30., y: 80. });
+
+        // Already inside a corner of a block : pushed out the shortest way
+        let result = sweep(&map, Aabb { x: 66., y: 60., w: 16., h: 16. }, 0., 0., false);
+        assert_eq!(normals(&result), vec![(0., -1.)]);
+        assert_eq!(result.contacts[0].penetration, 12.);
+        assert_eq!(result.aabb.y, 48.);
+    }
+
+    #[test]
+    fn sweep_on_one_way_platforms() {
+        let map = map_with(&[
+            "0000",
+            "0000",
+            "0-00",
+            "0000",
+        ]);
+        let above = Aabb { x: 36., y: 40., w: 16., h: 16. };
+
+        // Falling : lands on it, unless dropping through
+        let result = sweep(&map, above, 0., 40., false);
+        assert_eq!(normals(&result), vec![(0., -1.)]);
+        assert_eq!(result.aabb.y, 48.);
+        let result = sweep(&map, above, 0., 40., true);
+        assert!(result.contacts.is_empty());
+
+        // Going up or sideways : goes through it
+        let result = sweep(&map, Aabb { x: 36., y: 100., w: 16., h: 16. }, 0., -60., false);
+        assert!(result.contacts.is_empty());
+        let result = sweep(&map, Aabb { x: 0., y: 68., w: 16., h: 16. }, 60., 0., false);
+        assert!(result.contacts.is_empty());
+
+        // Already below its top : keeps falling
+        let result = sweep(&map, Aabb { x: 36., y: 60., w: 16., h: 16. }, 0., 20., false);
+        assert!(result.contacts.is_empty());
+    }
+
+    #[test]
+    fn sweep_entities_of_any_size() {
+        // A block at the bottom of the right side : only the lowest tiles of a tall entity touch it
+        let map = map_with(&[
+            "11111111",
+            "10000001",
+            "10000001",
+            "10000001",
+            "10000101",
+            "11111111",
+        ]);
+
+        for size in [16., 32., 48., 96.] {
+            let on_floor = Aabb { x: 32., y: 160. - size, w: size, h: size };
+
+            // Walls : the left one, and the block
+            let result = sweep(&map, on_floor, 300., 0., false);
+            assert_eq!(normals(&result), vec![(-1., 0.)], "size {}", size);
+            assert_eq!(result.aabb.x, 160. - size, "size {}", size);
+            let result = sweep(&map, on_floor, -300., 0., false);
+            assert_eq!(result.aabb.x, 32., "size {}", size);
+
+            // Floors : the ground, and the block under the right end of the entity only
+            let result = sweep(&map, Aabb { y: 32., ..on_floor }, 0., 300., false);
+            assert_eq!(normals(&result), vec![(0., -1.)], "size {}", size);
+            assert_eq!(result.aabb.bottom(), 160., "size {}", size);
+            let result = sweep(&map, Aabb { x: 176. - size, y: 32., ..on_floor }, 0., 300., false);
+            assert_eq!(result.aabb.bottom(), 128., "size {}", size);
+
+            // Ceiling
+            let result = sweep(&map, on_floor, 0., -300., false);
+            assert_eq!(normals(&result), vec![(0., 1.)], "size {}", size);
+            assert_eq!(result.aabb.top(), 32., "size {}", size);
+        }
+    }
+
+    #[test]
+    fn tiles_along_the_whole_side() {
+        let count = |x: Position, size: Position| Map::tiles_along(MapElementCollideType::Below, &Aabb { x, y: 0., w: size, h: size }).len();
+
+        assert_eq!([16., 32., 48., 96.].map(|size| count(32., size)), [1, 1, 2, 3]);
+        assert_eq!([16., 32., 48., 96.].map(|size| count(40., size)), [1, 2, 2, 4]);
+
+        let right = Map::tiles_along(MapElementCollideType::Right, &Aabb { x: 0., y: 16., w: 96., h: 96. });
+        assert_eq!(right, (0..=3).map(|lig| MapCoord { lig, col: 3 }).collect::<Vec<MapCoord>>());
+    }
+
+    #[test]
+    fn room_above_to_stand_up() {
+        let map = map_with(&[
+            "0000",
+            "11-0",
+            "0000",
+            "1111",
+        ]);
+        // Crouched box (half of its standing height) just under the tiles of the second row
+        let crouched = |x: Position| Aabb { x, y: 64., w: 32., h: 16. };
+
+        // Under a wall (even partly) : stays crouched
+        assert!(!map.has_room_above(&crouched(0.), 16.));
+        assert!(!map.has_room_above(&crouched(48.), 16.));
+
+        // Under a one-way platform, or nothing : stands up
+        assert!(map.has_room_above(&crouched(64.), 16.));
+        assert!(map.has_room_above(&crouched(96.), 16.));
+
+        // Lower, growing up to the bottom of the wall : stands up
+        assert!(map.has_room_above(&Aabb { x: 0., y: 80., w: 32., h: 16. }, 16.));
+    }
+
+    #[test]
+    fn ledge_within_reach() {
+        let mut map = map_with(&[
+            "0000",
+            "1001",
+            "1001",
+            "1111",
+        ]);
+        let hanging = |x: Position, y: Position| Aabb { x, y, w: 32., h: 32. };
+
+        // Top of the box just under the corner of the wall, on each side
+        assert_eq!(map.get_ledge(&hanging(64., 36.), MapElementCollideType::Right), Some(Position2d { x: 96., y: 32. }));
+        assert_eq!(map.get_ledge(&hanging(32., 36.), MapElementCollideType::Left), Some(Position2d { x: 32., y: 32. }));
+        assert_eq!(map.get_ledge(&hanging(64., 36.), MapElementCollideType::Above), None);
+
+        // Corner too far above the box, or box above the corner
+        assert_eq!(map.get_ledge(&hanging(64., 44.), MapElementCollideType::Right), None);
+        assert_eq!(map.get_ledge(&hanging(64., 28.), MapElementCollideType::Right), None);
+
+        // A tile on the ledge : no room to climb up
+        map.level[0][3] = '1';
+        assert_eq!(map.get_ledge(&hanging(64., 36.), MapElementCollideType::Right), None);
+    }
+
+    #[test]
+    fn slope_ground_under_a_tile_without_slope() {
+        // The top of the slope is at the bottom of the ladder above it
+        let map = map_with(&[
+            "0H00",
+            "0/00",
+            "1111",
+        ]);
+
+        let (ground, normal) = map.get_slope_ground(&Aabb { x: 52., y: 0., w: 16., h: 32. }).unwrap();
+        assert_eq!(ground, 36.);
+        assert!(normal.x < 0. && normal.y < 0.);
+        assert!(map.get_slope_ground(&Aabb { x: 72., y: 0., w: 16., h: 32. }).is_none());
+    }
+
+    #[test]
+    fn hazard_with_the_center_of_its_tile() {
+        let mut map = map_with(&[
+            "0000",
+            "^0X0",
+            "1111",
+        ]);
+        map.elements.insert('^', MapElement { name: "Spikes".to_string(), hazard: Some(MapHazard::Hurt(1)), ..Default::default() });
+        map.elements.insert('X', MapElement { name: "Lava".to_string(), hazard: Some(MapHazard::Kill), ..Default::default() });
+        let body = |x: Position| Body::new(Position2d { x, y: 32. }, Size2d { w: 32, h: 32 });
+
+        assert_eq!(map.get_hazard(&body(16.)), Some((MapHazard::Hurt(1), Position2d { x: 16., y: 48. })));
+        assert_eq!(map.get_hazard(&body(48.)), Some((MapHazard::Kill, Position2d { x: 80., y: 48. })));
+        assert_eq!(map.get_hazard(&body(32.)), None);
+    }
+
+    #[test]
+    fn checkpoints_keep_the_power_ups_taken_before_them() {
+        let mut map = map_with(&[
+            "0000000",
+            "0DC0J0C",
+            "1111111",
+        ]);
+        map.elements.insert('C', MapElement { name: "Checkpoint".to_string(), checkpoint: true, ..Default::default() });
+        map.elements.insert('D', MapElement { name: "Dash".to_string(), power_up: Some(MapPowerUp::Dash), ..Default::default() });
+        map.elements.insert('J', MapElement { name: "Double jump".to_string(), power_up: Some(MapPowerUp::DoubleJump), ..Default::default() });
+        map.start_level = map.level.clone();
+        let body = |x: Position| Body::new(Position2d { x, y: 32. }, Size2d { w: 32, h: 32 });
+
+        // No checkpoint yet : the level comes back as loaded
+        assert_eq!(map.take_power_up(&body(32.)), Some(MapPowerUp::Dash));
+        assert_eq!(map.level[1][1], MAP_EMPTY);
+        assert_eq!(map.take_power_up(&body(32.)), None);
+        map.restore_checkpoint();
+        assert_eq!(map.level[1][1], 'D');
+
+        // Taken before the checkpoint : stays taken
+        assert_eq!(map.take_power_up(&body(32.)), Some(MapPowerUp::Dash));
+        assert!(map.activate_checkpoint(&body(64.)));
+        assert_eq!(map.get_respawn_position(), Some(Position2d { x: 64., y: 32. }));
+
+        // Touching the active checkpoint again : ignored (the snapshot is not taken again)
+        assert_eq!(map.take_power_up(&body(128.)), Some(MapPowerUp::DoubleJump));
+        assert!(!map.activate_checkpoint(&body(64.)));
+
+        // Taken after the checkpoint : back at the respawn
+        map.restore_checkpoint();
+        assert_eq!(map.level[1][1], MAP_EMPTY);
+        assert_eq!(map.level[1][4], 'J');
+
+        // Another checkpoint : becomes the active one
+        assert!(map.activate_checkpoint(&body(192.)));
+        assert_eq!(map.get_respawn_position(), Some(Position2d { x: 192., y: 32. }));
+    }
+
+    #[test]
+    fn liquid_surface_at_the_top_of_the_column() {
+        let mut map = map_with(&[
+            "0000",
+            "1~~1",
+            "1~~1",
+            "1111",
+        ]);
+        map.elements.insert('~', MapElement { name: "Water".to_string(), liquid: Some(MapLiquid::default()), ..Default::default() });
+
+        assert_eq!(map.get_liquid_surface(40., 80.), Some(32.));
+        assert_eq!(map.get_liquid_surface(40., 40.), Some(32.));
+        assert_eq!(map.get_liquid_surface(40., 16.), None);
+        assert_eq!(map.get_liquid_surface(8., 80.), None);
+    }
+
+    #[test]
+    fn surface_under_any_part_of_the_feet() {
+        let mut map = map_with(&[
+            "0000",
+            "=111",
+        ]);
+        let ice = MapSurface { friction: 0.1, ..Default::default() };
+        map.elements.insert('=', MapElement { name: "Ice".to_string(), solid: true, surface: Some(ice), ..Default::default() });
+        let surface = |x: Position, w: Position| map.get_surface(&Aabb { x, y: 32. - w, w, h: w });
+
+        assert_eq!(surface(0., 32.), Some(ice));
+        // Only the edge of the feet on the ice
+        assert_eq!(surface(24., 32.), Some(ice));
+        assert_eq!(surface(32., 32.), None);
+        assert_eq!(surface(16., 96.), Some(ice));
+    }
+
+    #[test]
+    fn walk_down_and_up_45_degrees_slopes() {
+        // Flat ground, two slope tiles, flat ground 64 px lower
+        let map = map_with(&[
+            "00000000",
+            "11\\00000",
+            "111\\0000",
+            "11111111",
+        ]);
+
+        // Down : from the flat top onto the slope, and off it onto the flat bottom
+        let (aabb, airborne) = walk(&map, 0., 32., 150., 1.);
+        assert_eq!(airborne, 0);
+        assert!(aabb.x > 140.);
+        assert_eq!(aabb.bottom(), 96.);
+
+        // Up : back onto the flat top
+        let (aabb, airborne) = walk(&map, 180., 96., -150., 1.);
+        assert_eq!(airborne, 0);
+        assert!(aabb.x < 40.);
+        assert_eq!(aabb.bottom(), 32.);
+    }
+
+    #[test]
+    fn walk_down_and_up_22_5_degrees_slopes() {
+        // Flat ground, two slope tiles going down half a tile each, flat ground 32 px lower
+        let map = map_with(&[
+            "00000000",
+            "11cd0000",
+            "11111111",
+        ]);
+
+        let (aabb, airborne) = walk(&map, 0., 32., 150., 1.);
+        assert_eq!(airborne, 0);
+        assert!(aabb.x > 140.);
+        assert_eq!(aabb.bottom(), 64.);
+
+        let (aabb, airborne) = walk(&map, 180., 64., -150., 1.);
+        assert_eq!(airborne, 0);
+        assert!(aabb.x < 40.);
+        assert_eq!(aabb.bottom(), 32.);
+
+        // The other way (up to the right), on the ground at the middle of the slope
+        let map = map_with(&[
+            "00000000",
+            "0000ab11",
+            "11111111",
+        ]);
+        let (aabb, airborne) = walk(&map, 96., 64., 150., 0.4);
+        assert_eq!(airborne, 0);
+        let middle = aabb.x + 16.;
+        assert!(middle > 128. && middle < 192.);
+        assert_eq!(aabb.bottom(), map.get_slope_ground(&aabb).unwrap().0);
+    }
 }
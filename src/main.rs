@@ -8,21 +8,31 @@
     @Author : GCast31
 */
 
+pub mod camera;
+pub mod collision;
+pub mod effects;
 pub mod level;
 pub mod player;
+pub mod tiled;
+pub mod tileset;
+#[cfg(test)]
+mod testing;
 
 use std::any::TypeId;
 
-use game2d::game::common::{GAME_FONT_DEFAULT_, GAME_FONT_DEFAULT_SIZE, DeltaTime, Position2d, Position, Positionable, WithPosition, WithSize, Movable, Standing, Scale2d};
+use game2d::game::common::{GAME_FONT_DEFAULT_, GAME_FONT_DEFAULT_SIZE, DeltaTime, Position2d, Position, Velocity2d, Positionable, WithPosition, WithSize, Movable, Standing, Scale2d};
 use game2d::game::game::*;
 use game2d::game::inputs::Inputs;
 use game2d::game::sprites::Sprites;
 use game2d::graphics::color::Color;
 use game2d::graphics::fonts::FontsManager;
-use game2d::graphics::graphics::{Graphics, Drawable};
+use game2d::graphics::graphics::Graphics;
 use game2d::inputs::keyboard::Keys;
-use level::{Map, MapCoord};
-use player::Player;
+use camera::{Camera, CameraDrawable};
+use collision::{Aabb, SweepOptions};
+use effects::{Splash, SplashFrames};
+use level::{Map, MapCoord, MapElementCollideType, MapLiquid, MapSurface};
+use player::{Player, PlayerAbilities};
 
 
 // ################################################################################################################
@@ -32,28 +42,127 @@ pub const GAME_WINDOW_HEIGHT: u32 = 600;
 pub const GAME_WINDOW_WIDTH: u32 = 800;
 pub const GAME_SCALE: Scale2d = Scale2d {sx: 1. , sy: 1.};
 
-const SPRITE_FALLING: f32 = 20.;
+// Gravity in pixels / second²
+const SPRITE_FALLING: f32 = 1000.;
+// Slowest bounce (pixels / second) on a bouncy surface, the sprite lands below it
+const SPRITE_BOUNCE_MIN_SPEED: f32 = 100.;
+
+// Physics at a fixed rate (120 Hz), limited to some steps per frame when the game is too slow
+const PHYSICS_STEP: DeltaTime = 1. / 120.;
+const PHYSICS_MAX_STEPS: u32 = 8;
 
 // ################################################################################################################
 // #                                        S T R U C T U R E    G A M E                                          #
 // ################################################################################################################
 
+#[derive(PartialEq)]
+pub enum GameState {
+    Playing,
+    Complete,
+    GameOver,
+}
+
 pub struct Plateformer {
+    state: GameState,
+    accumulator: DeltaTime,
     actual_level: i32,
     map: Map,
+    camera: Camera,
     list_sprites: Sprites,
+    // Abilities of the player at the last checkpoint (or the start of the level), given back at the respawn
+    saved_abilities: PlayerAbilities,
+    splash_frames: Option<SplashFrames>,
+    splashes: Vec<Splash>,
 }
 
 impl Default for Plateformer {
     fn default() -> Self {
-        Plateformer { actual_level: 0, map: Map::new(), list_sprites: Sprites::new() }
+        Plateformer { state: GameState::Playing, accumulator: 0., actual_level: 0, map: Map::new(), camera: Camera::new(GAME_WINDOW_WIDTH, GAME_WINDOW_HEIGHT), list_sprites: Sprites::new(), saved_abilities: PlayerAbilities::default(), splash_frames: None, splashes: Vec::new() }
+    }
+}
+
+impl Plateformer {
+    /*
+     * next_level()
+     *
+     * @brief : Load the level after the actual one, the game is complete after the last one
+     */
+    fn next_level(&mut self, graphics: &mut Graphics) {
+        if !Map::level_exists(self.actual_level + 1) {
+            self.state = GameState::Complete;
+            return;
+        }
+
+        // The actual level changes only once the next one is loaded (else the player goes back to its respawn)
+        match self.map.load_level(graphics, self.actual_level + 1) {
+            Ok(()) => self.actual_level += 1,
+            Err(error) => eprintln!("{}", error),
+        }
+        self.respawn_player();
+
+        // Abilities are kept from one level to the next
+        for list in self.list_sprites.get_all_mut().values_mut() {
+            for sprite in list.iter_mut() {
+                if let Some(player) = sprite.downcast_mut::<Player>() {
+                    self.saved_abilities = *player.get_abilities();
+                }
+            }
+        }
+    }
+
+    /*
+     * respawn_player()
+     *
+     * @brief : Put the player (stopped, full health) at the last checkpoint, or the start of the level
+     */
+    fn respawn_player(&mut self) {
+        let player_start = self.map.get_respawn_position().unwrap_or(Position2d {x: 0., y: 0.});
+        self.camera.set_bounds(Some(self.map.get_pixel_size()));
+
+        for list in self.list_sprites.get_all_mut().values_mut() {
+            for sprite in list.iter_mut() {
+                if let Some(player) = sprite.downcast_mut::<Player>() {
+                    player.revive();
+                    player.set_position(player_start);
+                    player.save_previous_position();
+                    player.set_velocity(Velocity2d {vx: 0., vy: 0.});
+                    self.camera.look_at(player);
+                }
+            }
+        }
     }
 }
 
 // ################################################################################################################
 // #                                        S P E C I A L   T R A I T                                             #
 // ################################################################################################################
-pub trait SpriteCommonPlaterformerTrait: WithPosition + WithSize + Movable + Standing {} 
+pub trait SpriteCommonPlaterformerTrait: WithPosition + WithSize + Movable + Standing {
+    // One-way platforms do not stop the sprite (drop through)
+    fn is_dropping_through(&self) -> bool { false }
+    // The sprite stands on one-way platforms only
+    fn set_on_one_way(&mut self, _on_one_way: bool) {}
+    // The sprite climbs a ladder (no gravity)
+    fn is_climbing(&self) -> bool { false }
+    // The middle of the sprite is on a ladder
+    fn set_on_ladder(&mut self, _on_ladder: bool) {}
+    // The sprite is dying (falls through the map)
+    fn is_dying(&self) -> bool { false }
+    // Side of the wall the sprite moves into (Left / Right)
+    fn set_on_wall(&mut self, _wall: Option<MapElementCollideType>) {}
+    // The sprite dashes (no gravity)
+    fn is_dashing(&self) -> bool { false }
+    // Height the sprite wants to grow up (to stand up), and if the map leaves room for it
+    fn get_grow_height(&self) -> Position { 0. }
+    fn set_room_above(&mut self, _room_above: bool) {}
+    // The sprite hangs from a ledge (no gravity)
+    fn is_hanging(&self) -> bool { false }
+    // Side and corner of a ledge the falling sprite can grab (against a wall)
+    fn set_ledge(&mut self, _ledge: Option<(MapElementCollideType, Position2d)>) {}
+    // Liquid at the middle of the sprite, if its top is under it too, and the y of the liquid surface
+    fn set_liquid(&mut self, _liquid: Option<MapLiquid>, _under: bool, _surface: Option<Position>) {}
+    // Surface of the tile the sprite stands on
+    fn set_surface(&mut self, _surface: Option<MapSurface>) {}
+}
 
 // ################################################################################################################
 // #                                                   M A I N                                                    #
@@ -98,9 +207,22 @@ pub fn load(graphics: &mut Graphics, game: &mut Option<Plateformer>) {
 
 
     if let Some(game) = game {
+        // Load tiles of the level pack
+        if let Err(error) = game.map.load_tileset(tileset::TILESET_DEFAULT_FILENAME) {
+            eprintln!("{}", error);
+        }
+
         // Load initial level
         game.actual_level = 1;
-        game.map.load_level(game.actual_level);
+        if let Err(error) = game.map.load_level(graphics, game.actual_level) {
+            eprintln!("{}", error);
+        }
+
+        // Images of the effects (no splash without them)
+        match Splash::load_frames(graphics) {
+            Ok(frames) => game.splash_frames = Some(frames),
+            Err(error) => eprintln!("{}", error),
+        }
 
         // Add Player
         let player_position: Position2d;
@@ -112,6 +234,12 @@ pub fn load(graphics: &mut Graphics, game: &mut Option<Plateformer>) {
 
         let mut player = Player::new(graphics);
         player.set_position(player_position);
+        player.save_previous_position();
+
+        // Camera on the player
+        game.camera.set_bounds(Some(game.map.get_pixel_size()));
+        game.camera.look_at(&player);
+
         game.list_sprites.add(player);
     }
 }
@@ -122,83 +250,191 @@ pub fn load(graphics: &mut Graphics, game: &mut Option<Plateformer>) {
 #[allow(unused_variables)]
 pub fn update(graphics: &mut Graphics, game: &mut Option<Plateformer>, inputs: &mut Inputs, dt: DeltaTime) {
     if let Some(game) = game {
+        if game.state != GameState::Playing {
+            return;
+        }
 
-        // Sprites
-        let sprites = game.list_sprites.get_all_mut();
+        // Physics at fixed steps
+        for _ in 0..physics_steps(&mut game.accumulator, dt) {
+            // Sprites
+            let sprites = game.list_sprites.get_all_mut();
+            let mut level_done = false;
+            let mut player_dead = false;
+            let mut game_over = false;
+
+            for (typeid, list) in sprites.iter_mut() {
+                for sprite in list.iter_mut() {
+                   if let Some(player) = sprite.downcast_mut::<Player>() {
+                      player.save_previous_position();
+                      player.update(graphics, inputs, &PHYSICS_STEP);
+                      update_sprite(typeid, player, &game.map, &PHYSICS_STEP);
+                      if let (Some(position), Some(frames)) = (player.take_splash(), &game.splash_frames) {
+                          game.splashes.push(Splash::new(frames, position));
+                      }
+
+                      // Hazards
+                      if let Some((hazard, source)) = game.map.get_hazard(player) {
+                          player.hurt(hazard, source);
+                      }
+                      if player.is_dead() {
+                          player.set_abilities(game.saved_abilities);
+                          player_dead = true;
+                          game_over = player.get_lives() == 0;
+                      }
+
+                      if !player.is_dying() {
+                          // Power-ups
+                          if let Some(power_up) = game.map.take_power_up(player) {
+                              player.grant(power_up);
+                          }
+
+                          // Checkpoints
+                          if game.map.activate_checkpoint(player) {
+                              game.saved_abilities = *player.get_abilities();
+                          }
+
+                          // Exit of the level
+                          if game.map.get_tiles_under(player).iter().any(|element| element.exit) {
+                              level_done = true;
+                          }
+                      }
+                   }
+                }
+            }
 
-        for (typeid, list) in sprites.iter_mut() {
+            if game_over {
+                game.state = GameState::GameOver;
+                return;
+            }
+            if player_dead {
+                game.map.restore_checkpoint();
+                game.respawn_player();
+            }
+            if level_done {
+                game.next_level(graphics);
+                if game.state != GameState::Playing {
+                    return;
+                }
+            }
+        }
+
+        // Effects
+        for splash in game.splashes.iter_mut() {
+            splash.update(&dt);
+        }
+        game.splashes.retain(|splash| !splash.is_finished());
+
+        // Rendering between the last two steps
+        let alpha = game.accumulator / PHYSICS_STEP;
+        for list in game.list_sprites.get_all_mut().values_mut() {
             for sprite in list.iter_mut() {
-               if let Some(player) = sprite.downcast_mut::<Player>() {
-                  player.update(graphics, inputs, &dt);
-                  update_sprite(typeid, player, &game.map, &dt)
-               }
+                if let Some(player) = sprite.downcast_mut::<Player>() {
+                    player.set_render_alpha(alpha);
+                    game.camera.follow(&player.get_render_position(), player.get_size(), &dt);
+                }
             }
         }
     }
 }
 
-fn update_sprite<T: SpriteCommonPlaterformerTrait>(_typeid: &TypeId, sprite: &mut T, map: &Map, dt: &DeltaTime) {
-    let _sprite_position = sprite.get_position().clone();
-
-    // Collide detection
-    let mut collide = false;
-
-    let mut velocity = *sprite.get_mut_velocity();
+/*
+ * physics_steps()
+ *
+ * @brief : Number of fixed steps to run for a frame of dt seconds, the time left is kept for the next frame
+ */
+fn physics_steps(accumulator: &mut DeltaTime, dt: DeltaTime) -> u32 {
+    *accumulator = (*accumulator + dt).min(PHYSICS_STEP * PHYSICS_MAX_STEPS as DeltaTime);
+
+    let mut steps = 0;
+    while *accumulator >= PHYSICS_STEP {
+        *accumulator -= PHYSICS_STEP;
+        steps += 1;
+    }
 
-    let mut modify_position = false;
+    steps
+}
 
-    // -- Right 
-    if !collide && velocity.vx > 0. {
-        collide = map.collide(level::MapElementCollideType::Right, sprite);
+fn update_sprite<T: SpriteCommonPlaterformerTrait>(_typeid: &TypeId, sprite: &mut T, map: &Map, dt: &DeltaTime) {
+    let mut velocity = *sprite.get_velocity();
+    let mut gravity = if sprite.is_climbing() || sprite.is_dashing() || sprite.is_hanging() { 0. } else { SPRITE_FALLING };
+
+    // Liquid (at the middle of the sprite) : its gravity and drag
+    let aabb = Aabb::from_entity(sprite);
+    if let Some(liquid) = map.get_liquid_at(aabb.x + aabb.w / 2., aabb.y + aabb.h / 2.) {
+        gravity *= liquid.gravity_scale;
+        let kept = (1. - liquid.drag * dt).max(0.);
+        velocity.vx *= kept;
+        velocity.vy *= kept;
     }
 
-    // -- Left
-    if !collide && velocity.vx < 0. {
-        collide = map.collide(level::MapElementCollideType::Left, sprite);
-    }
+    // Move of the step, with gravity (1/2.g.dt² keeps the trajectory independent of the step)
+    let mut movement = Velocity2d {
+        vx: velocity.vx * dt,
+        vy: velocity.vy * dt + 0.5 * gravity * dt * dt,
+    };
 
-    // -- Stop !
-    if collide {
-        velocity.vx = 0.;
-        sprite.set_x(((sprite.get_position().x + (sprite.get_size().w as Position / 2.) / sprite.get_size().w as Position)).floor());
+    // Conveyor : carries the standing sprite, without changing its own velocity
+    if let Some(surface) = map.get_surface(&aabb).filter(|_| sprite.get_standing()) {
+        movement.vx += surface.conveyor * dt;
     }
-    collide = false;
-
-    // Above
-    if !collide && velocity.vy < 0. {
-        collide = map.collide(level::MapElementCollideType::Above, sprite);
-        if collide {
-          velocity.vy = 0.;
-          sprite.set_y(((sprite.get_position().y + (sprite.get_size().h as Position / 2.) / sprite.get_size().h as Position)).floor());
-          modify_position = true;
-        }
+    velocity.vy += gravity * dt;
+
+    // Dying : no collision, falls out of the map
+    if sprite.is_dying() {
+        let position = *sprite.get_position();
+        sprite.set_position(Position2d { x: position.x + movement.vx, y: position.y + movement.vy });
+        sprite.set_velocity(velocity);
+        return;
     }
 
-    // Below
-    if sprite.get_standing() || velocity.vy > 0. {
-        collide = map.collide(level::MapElementCollideType::Below, sprite);
-        if collide {
-            sprite.set_standing(true);
+    // Collide detection
+    let options = SweepOptions { drop_through: sprite.is_dropping_through(), grounded: sprite.get_standing() };
+    let result = map.sweep(&aabb, &movement, &options);
+    sprite.set_position(result.aabb.get_position());
+
+    let falling_speed = velocity.vy;
+    let mut standing = false;
+    let mut wall = None;
+    for contact in result.contacts.iter() {
+        // -- Stop !
+        if contact.is_wall() && velocity.vx * contact.normal.x < 0. {
+            velocity.vx = 0.;
+            wall = Some(if contact.normal.x < 0. { MapElementCollideType::Right } else { MapElementCollideType::Left });
+        }
+        // Above
+        if contact.is_ceiling() && velocity.vy < 0. {
             velocity.vy = 0.;
-            sprite.set_y(((sprite.get_position().y + (sprite.get_size().w as Position / 2.) / sprite.get_size().h as Position)).floor());
-            modify_position = true;
         }
-        else {
-            sprite.set_standing(false);
+        // Below
+        if contact.is_ground() {
+            standing = true;
+            if velocity.vy > 0. {
+                velocity.vy = 0.;
+            }
         }
     }
-    // Sprite falling
-    if sprite.get_standing() == false {
-        velocity.vy += SPRITE_FALLING * dt;
-    }
 
-    if modify_position {
-        //let coord = MapCoord::from(sprite.get_position().clone());
-        //println!("{:?}, {:?}", _sprite_position.x, _sprite_position.y);
-        //println!("{:?}, {:?}", coord.col, coord.lig);
-        //sprite.set_position(MapCoord::to_position2d(coord));
+    // Bouncy surface : gives back a part of the falling speed
+    let surface = map.get_surface(&result.aabb).filter(|_| standing);
+    if let Some(surface) = surface {
+        if falling_speed * surface.bounce >= SPRITE_BOUNCE_MIN_SPEED {
+            velocity.vy = -falling_speed * surface.bounce;
+            standing = false;
+        }
     }
 
+    sprite.set_standing(standing);
+    sprite.set_surface(surface.filter(|_| standing));
+    sprite.set_on_one_way(standing && map.is_on_one_way(&result.aabb));
+    sprite.set_on_ladder(map.is_on_ladder(&result.aabb));
+    sprite.set_on_wall(wall);
+    sprite.set_ledge(wall.filter(|_| !standing && velocity.vy > 0.)
+        .and_then(|side| map.get_ledge(&result.aabb, side).map(|corner| (side, corner))));
+    let (center_x, center_y) = (result.aabb.x + result.aabb.w / 2., result.aabb.y + result.aabb.h / 2.);
+    sprite.set_liquid(map.get_liquid_at(center_x, center_y), map.get_liquid_at(center_x, result.aabb.top()).is_some(),
+                      map.get_liquid_surface(center_x, center_y));
+    let grow_height = sprite.get_grow_height();
+    sprite.set_room_above(grow_height <= 0. || map.has_room_above(&result.aabb, grow_height));
     sprite.set_velocity(velocity);
 }
 
@@ -218,16 +454,42 @@ pub fn keypressed(graphics: &mut Graphics, game: &mut Option<Plateformer>, key:
 pub fn draw(graphics: &mut Graphics, game: &mut Option<Plateformer>, inputs: &mut Inputs, fonts_manager: &mut Option<FontsManager>) {
    if let Some(game) = game {
         // Draw the map
-        game.map.draw(graphics);
+        game.map.draw_with_camera(graphics, &game.camera);
 
         // Debug
         if let Some(fonts_manager) = fonts_manager {
-            if let Some(element) = game.map.get_tile_at(inputs.mouse.get_x(), inputs.mouse.get_y()) {
+            let mouse = game.camera.to_world(inputs.mouse.get_x(), inputs.mouse.get_y());
+            if let Some(element) = game.map.get_tile_at(mouse.x, mouse.y) {
                 graphics.print(fonts_manager, element.filename.clone(), 0., GAME_WINDOW_HEIGHT as Position - 20., Option::None);
             
             } else {
                 graphics.print(fonts_manager, "Nothing".to_string(), 0., GAME_WINDOW_HEIGHT as Position - 20., Option::None);
             }
+
+            if game.state == GameState::Complete {
+                graphics.print(fonts_manager, "GAME COMPLETE".to_string(), GAME_WINDOW_WIDTH as Position / 2. - 50., GAME_WINDOW_HEIGHT as Position / 2., Some(Color::WHITE));
+            }
+            if game.state == GameState::GameOver {
+                graphics.print(fonts_manager, "GAME OVER".to_string(), GAME_WINDOW_WIDTH as Position / 2. - 40., GAME_WINDOW_HEIGHT as Position / 2., Some(Color::WHITE));
+            }
+
+            // Health and lives
+            for list in game.list_sprites.get_all_mut().values_mut() {
+                for sprite in list.iter_mut() {
+                    if let Some(player) = sprite.downcast_mut::<Player>() {
+                        let mut status = format!("Health {}/{}  Lives {}", player.get_health(), player.get_config().health, player.get_lives());
+                        if let Some(breath) = player.get_breath() {
+                            status += &format!("  Breath {:.1}", breath.max(0.));
+                        }
+                        graphics.print(fonts_manager, status, 0., 0., Some(Color::WHITE));
+                    }
+                }
+            }
+        }
+
+        // Draw effects
+        for splash in game.splashes.iter_mut() {
+            splash.draw_with_camera(graphics, &game.camera);
         }
 
         // Draw sprites
@@ -236,7 +498,7 @@ pub fn draw(graphics: &mut Graphics, game: &mut Option<Plateformer>, inputs: &mu
         for (typeid, list) in sprites.iter_mut() {
             for sprite in list.iter_mut() {
                 if let Some(player) = sprite.downcast_mut::<Player>() {
-                    player.draw(graphics);
+                    player.draw_with_camera(graphics, &game.camera);
                 }
             }
         } 
@@ -250,4 +512,76 @@ pub fn draw(graphics: &mut Graphics, game: &mut Option<Plateformer>, inputs: &mu
 #[allow(unused_variables)]
 pub fn quit(graphics: &mut Graphics, game: &mut Option<Plateformer>) {
     println!("Bye");
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game2d::game::common::Size2d;
+    use player::{jump_cut, JumpTimers, PlayerConfig};
+    use testing::Body;
+
+    // Height reached by a jump with Up held for a time, in frames of 1 / fps seconds :
+    // the jump of Player::update (JumpTimers, jump_cut) then the move of update_sprite, with the time of the frame
+    fn jump_apex(fps: f32, hold: f32) -> Position {
+        let config = PlayerConfig::default();
+        let map = Map::new();
+        let mut body = Body { standing: true, ..Body::new(Position2d { x: 0., y: 0. }, Size2d { w: 32, h: 32 }) };
+        let dt = 1. / fps;
+
+        let mut timers = JumpTimers::default();
+        let mut jumping = false;
+        let mut apex: Position = 0.;
+        for frame in 0..(fps as u32) {
+            let up = (frame as f32) < hold * fps - 0.5;
+            timers.update(&config, body.standing, frame == 0, dt);
+            if timers.can_jump(body.standing) {
+                body.velocity.vy = config.jump_velocity;
+                body.standing = false;
+                jumping = true;
+                timers = JumpTimers::default();
+            }
+            timers.end_step(dt);
+            if !up {
+                body.velocity.vy = jump_cut(&config, body.velocity.vy, jumping);
+                jumping = false;
+            }
+
+            update_sprite(&TypeId::of::<Body>(), &mut body, &map, &dt);
+            apex = apex.min(body.position.y);
+        }
+
+        -apex
+    }
+
+    #[test]
+    fn jump_apex_is_the_same_at_30_60_and_144_fps() {
+        let velocity = PlayerConfig::default().jump_velocity;
+        let expected = velocity * velocity / (2. * SPRITE_FALLING);
+
+        // Up held : the full jump
+        for fps in [30., 60., 144.] {
+            let apex = jump_apex(fps, 1.);
+            assert!((apex - expected).abs() < 2., "apex {} at {} fps, expected {}", apex, fps, expected);
+        }
+
+        // Up released after 1/6 second : the same lower jump
+        let low = jump_apex(60., 1. / 6.);
+        assert!(low < expected - 10.);
+        for fps in [30., 144.] {
+            let apex = jump_apex(fps, 1. / 6.);
+            assert!((apex - low).abs() < 2., "low apex {} at {} fps, {} at 60 fps", apex, fps, low);
+        }
+    }
+
+    #[test]
+    fn physics_steps_keep_the_time_left() {
+        let mut accumulator = 0.;
+        let steps: u32 = (0..30).map(|_| physics_steps(&mut accumulator, 1. / 30.)).sum();
+        assert_eq!(steps, 120);
+
+        // A long frame runs a limited number of steps
+        let mut accumulator = 0.;
+        assert_eq!(physics_steps(&mut accumulator, 1.), PHYSICS_MAX_STEPS);
+    }
+}
@@ -1,34 +1,279 @@
-use game2d::{game::{common::{Position2d, Velocity2d, Size2d, DeltaTime, Sizable, Positionable, Movable, WithPosition, WithSize, Standing, Transformation}, inputs::Inputs, game::Updatable, sprites::SpriteTrait}, graphics::{graphics::{Graphics, DrawMode, Drawable}, color::Color, images::{ImageInformations, Image}, self}, inputs::keyboard::Keys, animations::{animation::{Animation, self}, manager::AnimationsManager}};
+use game2d::{game::{common::{Position, Position2d, Velocity2d, Size2d, DeltaTime, Sizable, Positionable, Movable, WithPosition, WithSize, Standing, Transformation}, inputs::Inputs, game::Updatable, sprites::SpriteTrait}, graphics::{graphics::{Graphics, DrawMode, Drawable}, color::Color, images::{ImageInformations, Image}, self}, inputs::keyboard::Keys, animations::{animation::{Animation, self}, manager::AnimationsManager}};
 
-use crate::{SpriteCommonPlaterformerTrait, level::MAP_TILE_SIZE};
+use crate::{SpriteCommonPlaterformerTrait, camera::{Camera, CameraDrawable}, level::{MapElementCollideType, MapHazard, MapLiquid, MapPowerUp, MapSurface, MAP_TILE_SIZE}};
 
-const PLAYER_ACCEL: f32 = 150.;
-const PLAYER_FRICTION: f32 = 150.;
+// Speeds in pixels / second, accelerations in pixels / second²
+const PLAYER_ACCEL: f32 = 1500.;
+const PLAYER_FRICTION: f32 = 900.;
 const PLAYER_MAX_SPEED: f32 = 150.; 
-const PLAYER_JUMP_VELOCITY: f32 = -400.;
+// Crouch (Down) : height of the hitbox, highest speed, and slide when crouching faster than a speed
+const PLAYER_CROUCH_HEIGHT: u32 = MAP_TILE_SIZE as u32 / 2;
+const PLAYER_CROUCH_SPEED: f32 = 60.;
+const PLAYER_SLIDE_MIN_SPEED: f32 = 120.;
+const PLAYER_SLIDE_FRICTION: f32 = 250.;
+// Ledge : time (seconds) to climb up, and without grabbing again after letting go
+const PLAYER_LEDGE_CLIMB_TIME: f32 = 0.25;
+const PLAYER_LEDGE_COOLDOWN: f32 = 0.3;
+const PLAYER_JUMP_VELOCITY: f32 = -460.;
+// Part of the upward speed kept when Up is released during a jump
+const PLAYER_JUMP_CUT: f32 = 0.5;
+// Times (seconds) a jump is still allowed after leaving the ground, and remembered before landing
+const PLAYER_COYOTE_TIME: f32 = 0.1;
+const PLAYER_JUMP_BUFFER_TIME: f32 = 0.1;
+// Wall : highest fall speed pressing into it, jump away from it, and time (seconds) Left / Right are ignored after
+const PLAYER_WALL_SLIDE_SPEED: f32 = 80.;
+const PLAYER_WALL_JUMP_VELOCITY: Velocity2d = Velocity2d { vx: 250., vy: -420. };
+const PLAYER_WALL_JUMP_LOCK_TIME: f32 = 0.15;
+// Power-ups : second jump in the air, and dash (Left / Right twice quickly) with its speed and times (seconds)
+const PLAYER_DOUBLE_JUMP_VELOCITY: f32 = -400.;
+const PLAYER_DASH_SPEED: f32 = 400.;
+const PLAYER_DASH_TIME: f32 = 0.15;
+const PLAYER_DASH_COOLDOWN: f32 = 0.6;
+const PLAYER_DASH_TAP_TIME: f32 = 0.25;
+// Time (seconds) the one-way platforms are ignored after Down + Jump
+const PLAYER_DROP_THROUGH_TIME: f32 = 0.15;
+const PLAYER_CLIMB_SPEED: f32 = 100.;
+// Health points of a life, lives at the start of the game
+const PLAYER_HEALTH: u32 = 3;
+const PLAYER_LIVES: u32 = 3;
+// After being hurt : push away (pixels / second), time (seconds) without damage and blinking period
+const PLAYER_KNOCKBACK: Velocity2d = Velocity2d { vx: 200., vy: -250. };
+const PLAYER_INVINCIBILITY_TIME: f32 = 1.5;
+const PLAYER_BLINK_PERIOD: f32 = 0.1;
+// Death : jump (pixels / second) out of the map, and time (seconds) before the respawn
+const PLAYER_DEATH_VELOCITY: f32 = -350.;
+const PLAYER_DEATH_TIME: f32 = 1.5;
 
 pub trait PlayerTrait {}
 
+// ################################################################################################################
+// #                                          P L A Y E R   C O N F I G                                           #
+// ################################################################################################################
+pub struct PlayerConfig {
+    pub jump_velocity: f32,
+    pub jump_cut: f32,
+    pub coyote_time: f32,
+    pub jump_buffer_time: f32,
+    pub wall_slide_speed: f32,
+    pub wall_jump_velocity: Velocity2d,
+    pub wall_jump_lock_time: f32,
+    pub double_jump_velocity: f32,
+    pub dash_speed: f32,
+    pub dash_time: f32,
+    pub dash_cooldown: f32,
+    pub dash_tap_time: f32,
+    pub health: u32,
+    pub lives: u32,
+    pub knockback: Velocity2d,
+    pub invincibility_time: f32,
+    pub blink_period: f32,
+}
+
+impl Default for PlayerConfig {
+    fn default() -> Self {
+        Self {
+            jump_velocity: PLAYER_JUMP_VELOCITY,
+            jump_cut: PLAYER_JUMP_CUT,
+            coyote_time: PLAYER_COYOTE_TIME,
+            jump_buffer_time: PLAYER_JUMP_BUFFER_TIME,
+            wall_slide_speed: PLAYER_WALL_SLIDE_SPEED,
+            wall_jump_velocity: PLAYER_WALL_JUMP_VELOCITY,
+            wall_jump_lock_time: PLAYER_WALL_JUMP_LOCK_TIME,
+            double_jump_velocity: PLAYER_DOUBLE_JUMP_VELOCITY,
+            dash_speed: PLAYER_DASH_SPEED,
+            dash_time: PLAYER_DASH_TIME,
+            dash_cooldown: PLAYER_DASH_COOLDOWN,
+            dash_tap_time: PLAYER_DASH_TAP_TIME,
+            health: PLAYER_HEALTH,
+            lives: PLAYER_LIVES,
+            knockback: PLAYER_KNOCKBACK,
+            invincibility_time: PLAYER_INVINCIBILITY_TIME,
+            blink_period: PLAYER_BLINK_PERIOD,
+        }
+    }
+}
+
+// ################################################################################################################
+// #                                       P L A Y E R   A B I L I T I E S                                        #
+// ################################################################################################################
+// Given by the power-ups, kept from one level to the next
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlayerAbilities {
+    pub double_jump: bool,
+    pub dash: bool,
+}
+
+// ################################################################################################################
+// #                                          J U M P   T I M E R S                                               #
+// ################################################################################################################
+// Coyote time and jump buffer (seconds left), kept apart from the inputs and the graphics
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct JumpTimers {
+    // A jump is still allowed after leaving the ground
+    pub coyote: f32,
+    // A jump pressed in the air is remembered until landing
+    pub buffer: f32,
+}
+
+impl JumpTimers {
+    /*
+     * update()
+     *
+     * @brief : Start of a step : coyote time full on the ground (running out in the air), a new press fills the buffer
+     */
+    pub fn update(&mut self, config: &PlayerConfig, standing: bool, pressed: bool, dt: f32) {
+        self.coyote = if standing { config.coyote_time } else { (self.coyote - dt).max(0.) };
+        if pressed {
+            self.buffer = config.jump_buffer_time;
+        }
+    }
+
+    /*
+     * can_jump()
+     *
+     * @brief : A jump is wanted, with the ground under the player (or a moment ago)
+     */
+    pub fn can_jump(&self, standing: bool) -> bool {
+        self.buffer > 0. && (standing || self.coyote > 0.)
+    }
+
+    /*
+     * end_step()
+     *
+     * @brief : End of a step : the remembered press runs out
+     */
+    pub fn end_step(&mut self, dt: f32) {
+        self.buffer = (self.buffer - dt).max(0.);
+    }
+}
+
+/*
+ * jump_cut()
+ *
+ * @brief : Vertical velocity once Up is released : lowered when still going up after a jump
+ */
+pub fn jump_cut(config: &PlayerConfig, vy: f32, jumping: bool) -> f32 {
+    if jumping && vy < 0. { vy * config.jump_cut } else { vy }
+}
+
+// ################################################################################################################
+// #                                                 P L A Y E R                                                  #
+// ################################################################################################################
+
 pub struct Player {
     position: Position2d,
+    previous_position: Position2d,
+    render_alpha: f32,
     velocity: Velocity2d,
     size: Size2d,
     standing: bool,
+    on_one_way: bool,
+    drop_through: f32,
+    on_ladder: bool,
+    climbing: bool,
+    config: PlayerConfig,
+    health: u32,
+    lives: u32,
+    invincible: f32,
+    dying: Option<f32>,
     jump_ready: bool,
+    jumping: bool,
+    jump_timers: JumpTimers,
+    on_wall: Option<MapElementCollideType>,
+    wall: Option<MapElementCollideType>,
+    wall_time: f32,
+    wall_jump_lock: f32,
+    abilities: PlayerAbilities,
+    air_jump: bool,
+    dashing: f32,
+    dash_cooldown: f32,
+    // Direction and time left of the first tap of a dash
+    dash_tap: Option<(f32, f32)>,
+    left_down: bool,
+    right_down: bool,
+    crouching: bool,
+    sliding: bool,
+    room_above: bool,
+    ledge: Option<(MapElementCollideType, Position2d)>,
+    hanging: Option<(MapElementCollideType, Position2d)>,
+    ledge_climb: f32,
+    ledge_cooldown: f32,
+    liquid: Option<MapLiquid>,
+    under_liquid: bool,
+    // Time left under the liquid before drowning
+    breath: Option<f32>,
+    splash: Option<Position2d>,
+    surface: Option<MapSurface>,
     animations: AnimationsManager,
 }
 
 impl SpriteTrait for Player {}
 
-impl SpriteCommonPlaterformerTrait for Player {}
+impl SpriteCommonPlaterformerTrait for Player {
+    fn is_dropping_through(&self) -> bool {
+        // A ladder goes through the one-way platforms
+        self.drop_through > 0. || self.climbing
+    }
+    fn set_on_one_way(&mut self, on_one_way: bool) {
+        self.on_one_way = on_one_way;
+    }
+    fn is_climbing(&self) -> bool {
+        self.climbing
+    }
+    fn set_on_ladder(&mut self, on_ladder: bool) {
+        self.on_ladder = on_ladder;
+    }
+    fn is_dying(&self) -> bool {
+        self.dying.is_some()
+    }
+    fn set_on_wall(&mut self, wall: Option<MapElementCollideType>) {
+        self.on_wall = wall;
+    }
+    fn is_dashing(&self) -> bool {
+        self.dashing > 0.
+    }
+    fn get_grow_height(&self) -> Position {
+        if self.crouching { MAP_TILE_SIZE - PLAYER_CROUCH_HEIGHT as Position } else { 0. }
+    }
+    fn set_room_above(&mut self, room_above: bool) {
+        self.room_above = room_above;
+    }
+    fn is_hanging(&self) -> bool {
+        self.hanging.is_some()
+    }
+    fn set_ledge(&mut self, ledge: Option<(MapElementCollideType, Position2d)>) {
+        self.ledge = ledge;
+    }
+    fn set_liquid(&mut self, liquid: Option<MapLiquid>, under: bool, surface: Option<Position>) {
+        if let Some(surface) = surface.filter(|_| liquid.is_some() && self.liquid.is_none() && self.dying.is_none()) {
+            // Splash centered on the player, standing on the surface
+            self.splash = Some(Position2d { x: self.position.x + (self.size.w as Position - MAP_TILE_SIZE) / 2., y: surface - MAP_TILE_SIZE });
+        }
+        self.liquid = liquid;
+        self.under_liquid = under;
+    }
+    fn set_surface(&mut self, surface: Option<MapSurface>) {
+        self.surface = surface;
+    }
+}
 
 impl Drawable for Player {
     fn draw(&mut self, graphics: &mut Graphics) {
+        self.draw_with_camera(graphics, &Camera::default());
+    }
+}
+
+impl CameraDrawable for Player {
+    fn draw_with_camera(&mut self, graphics: &mut Graphics, camera: &Camera) {
+        let position = camera.to_screen(self.get_render_position());
+        // Invincible : visible one blinking period out of two
+        let hidden = self.is_invincible() && self.config.blink_period > 0.
+            && (self.invincible / self.config.blink_period) as u32 % 2 == 1;
         let image = self.animations.run_current();
-        if let Some(image) = image {
+        if let Some(image) = image.filter(|_| !hidden) {
           let scalex = (self.size.w / image.get_width()) as Transformation;
           let scaley = (self.size.h / image.get_height()) as Transformation;
-          graphics.draw_full(image.as_ref(), self.position.x, self.position.y, 0., scalex, scaley, 0., 0.);
+          graphics.draw_full(image.as_ref(), position.x, position.y, 0., scalex, scaley, 0., 0.);
         }
         // graphics.rectangle(
         //     DrawMode::Fill, 
@@ -92,19 +337,465 @@ impl Player {
         animation.add(Box::new(image)); 
         animations.add("jump".to_owned(), animation).unwrap();
 
+        let mut animation = Animation::new();
+        animation.set_timer(150);
+        let image = graphics.new_image("images/player/climb1.png").unwrap();
+        animation.add(Box::new(image)); 
+        let image = graphics.new_image("images/player/climb2.png").unwrap();
+        animation.add(Box::new(image)); 
+        animations.add("climb".to_owned(), animation).unwrap();
+
+        let mut animation = Animation::new();
+        animation.set_timer(150);
+        let image = graphics.new_image("images/player/die1.png").unwrap();
+        animation.add(Box::new(image)); 
+        let image = graphics.new_image("images/player/die2.png").unwrap();
+        animation.add(Box::new(image)); 
+        animations.add("die".to_owned(), animation).unwrap();
+
+        let mut animation = Animation::new();
+        animation.set_timer(100);
+        let image = graphics.new_image("images/player/hurt.png").unwrap();
+        animation.add(Box::new(image)); 
+        animations.add("hurt".to_owned(), animation).unwrap();
+
+        let mut animation = Animation::new();
+        animation.set_timer(100);
+        let image = graphics.new_image("images/player/wall_slide.png").unwrap();
+        animation.add(Box::new(image)); 
+        animations.add("wall_slide".to_owned(), animation).unwrap();
+
+        let mut animation = Animation::new();
+        animation.set_timer(100);
+        let image = graphics.new_image("images/player/wall_jump1.png").unwrap();
+        animation.add(Box::new(image)); 
+        let image = graphics.new_image("images/player/wall_jump2.png").unwrap();
+        animation.add(Box::new(image)); 
+        animations.add("wall_jump".to_owned(), animation).unwrap();
+
+        let mut animation = Animation::new();
+        animation.set_timer(80);
+        let image = graphics.new_image("images/player/double_jump1.png").unwrap();
+        animation.add(Box::new(image)); 
+        let image = graphics.new_image("images/player/double_jump2.png").unwrap();
+        animation.add(Box::new(image)); 
+        animations.add("double_jump".to_owned(), animation).unwrap();
+
+        let mut animation = Animation::new();
+        animation.set_timer(50);
+        let image = graphics.new_image("images/player/dash1.png").unwrap();
+        animation.add(Box::new(image)); 
+        let image = graphics.new_image("images/player/dash2.png").unwrap();
+        animation.add(Box::new(image)); 
+        animations.add("dash".to_owned(), animation).unwrap();
+
+        let mut animation = Animation::new();
+        animation.set_timer(100);
+        let image = graphics.new_image("images/player/crouch1.png").unwrap();
+        animation.add(Box::new(image)); 
+        let image = graphics.new_image("images/player/crouch2.png").unwrap();
+        animation.add(Box::new(image)); 
+        animations.add("crouch".to_owned(), animation).unwrap();
+
+        let mut animation = Animation::new();
+        animation.set_timer(100);
+        let image = graphics.new_image("images/player/slide.png").unwrap();
+        animation.add(Box::new(image)); 
+        animations.add("slide".to_owned(), animation).unwrap();
+
+        let mut animation = Animation::new();
+        animation.set_timer(100);
+        let image = graphics.new_image("images/player/hang.png").unwrap();
+        animation.add(Box::new(image)); 
+        animations.add("hang".to_owned(), animation).unwrap();
+
+        let mut animation = Animation::new();
+        animation.set_timer(60);
+        let image = graphics.new_image("images/player/ledge_climb1.png").unwrap();
+        animation.add(Box::new(image)); 
+        let image = graphics.new_image("images/player/ledge_climb2.png").unwrap();
+        animation.add(Box::new(image)); 
+        let image = graphics.new_image("images/player/ledge_climb3.png").unwrap();
+        animation.add(Box::new(image)); 
+        let image = graphics.new_image("images/player/idle1.png").unwrap();
+        animation.add(Box::new(image)); 
+        animations.add("ledge_climb".to_owned(), animation).unwrap();
+
+        let mut animation = Animation::new();
+        animation.set_timer(150);
+        let image = graphics.new_image("images/player/swim1.png").unwrap();
+        animation.add(Box::new(image)); 
+        let image = graphics.new_image("images/player/swim2.png").unwrap();
+        animation.add(Box::new(image)); 
+        let image = graphics.new_image("images/player/swim3.png").unwrap();
+        animation.add(Box::new(image)); 
+        animations.add("swim".to_owned(), animation).unwrap();
+
         animations.set_current("idle".to_owned()).unwrap();
 
         Self {
             position: Position2d { x: 0., y: 0. },
+            previous_position: Position2d { x: 0., y: 0. },
+            render_alpha: 1.,
             velocity: Velocity2d { vx: 0., vy: 0. },
             size: Size2d { h: MAP_TILE_SIZE as u32, w: MAP_TILE_SIZE as u32 },
             standing: true,
+            on_one_way: false,
+            drop_through: 0.,
+            on_ladder: false,
+            climbing: false,
+            config: PlayerConfig::default(),
+            health: PLAYER_HEALTH,
+            lives: PLAYER_LIVES,
+            invincible: 0.,
+            dying: None,
             jump_ready: true,
+            jumping: false,
+            jump_timers: JumpTimers::default(),
+            on_wall: None,
+            wall: None,
+            wall_time: 0.,
+            wall_jump_lock: 0.,
+            abilities: PlayerAbilities::default(),
+            air_jump: false,
+            dashing: 0.,
+            dash_cooldown: 0.,
+            dash_tap: None,
+            left_down: false,
+            right_down: false,
+            crouching: false,
+            sliding: false,
+            room_above: true,
+            ledge: None,
+            hanging: None,
+            ledge_climb: 0.,
+            ledge_cooldown: 0.,
+            liquid: None,
+            under_liquid: false,
+            breath: None,
+            splash: None,
+            surface: None,
             animations: animations,
         }
     }
 }
 
+impl Player {
+    /*
+     * save_previous_position()
+     *
+     * @brief : Keep the position before a physics step (for the interpolation of the rendering)
+     */
+    pub fn save_previous_position(&mut self) {
+        self.previous_position = self.position;
+    }
+
+    pub fn get_config(&self) -> &PlayerConfig {
+        &self.config
+    }
+
+    pub fn get_abilities(&self) -> &PlayerAbilities {
+        &self.abilities
+    }
+
+    pub fn set_abilities(&mut self, abilities: PlayerAbilities) {
+        self.abilities = abilities;
+    }
+
+    /*
+     * grant()
+     *
+     * @brief : Ability given by a power-up
+     */
+    pub fn grant(&mut self, power_up: MapPowerUp) {
+        match power_up {
+            MapPowerUp::DoubleJump => self.abilities.double_jump = true,
+            MapPowerUp::Dash => self.abilities.dash = true,
+        }
+    }
+
+    pub fn get_health(&self) -> u32 {
+        self.health
+    }
+
+    pub fn get_lives(&self) -> u32 {
+        self.lives
+    }
+
+    /*
+     * get_breath()
+     *
+     * @brief : Time left before drowning (only under a liquid with a breath time)
+     */
+    pub fn get_breath(&self) -> Option<f32> {
+        self.breath
+    }
+
+    /*
+     * take_splash()
+     *
+     * @brief : Position of the splash (on the liquid surface) where the player entered a liquid since the last call
+     */
+    pub fn take_splash(&mut self) -> Option<Position2d> {
+        self.splash.take()
+    }
+
+    pub fn is_invincible(&self) -> bool {
+        self.invincible > 0.
+    }
+
+    /*
+     * hurt()
+     *
+     * @brief : Damage of a hazard : health lost, pushed away from the hazard (source) and invincible for a while
+     *          (a "hurt" is ignored while invincible, a "kill" is not)
+     */
+    pub fn hurt(&mut self, hazard: MapHazard, source: Position2d) {
+        if self.dying.is_some() {
+            return;
+        }
+
+        match hazard {
+            MapHazard::Hurt(_) if self.is_invincible() => {},
+            MapHazard::Hurt(damage) => {
+                self.health = self.health.saturating_sub(damage);
+                if self.health == 0 {
+                    self.die();
+                    return;
+                }
+
+                // Knockback
+                let center_x = self.position.x + self.size.w as Position / 2.;
+                let direction = if center_x < source.x { -1. } else { 1. };
+                self.velocity = Velocity2d { vx: direction * self.config.knockback.vx, vy: self.config.knockback.vy };
+                self.standing = false;
+                self.climbing = false;
+                self.jumping = false;
+                self.jump_timers.coyote = 0.;
+                self.stop_moves();
+                self.invincible = self.config.invincibility_time;
+                self.animations.set_current("hurt".to_owned()).unwrap();
+            },
+            MapHazard::Kill => self.die(),
+        }
+    }
+
+    /*
+     * die()
+     *
+     * @brief : Lose a life and start the death animation (thrown up, then spinning out of the map)
+     */
+    fn die(&mut self) {
+        self.health = 0;
+        self.lives = self.lives.saturating_sub(1);
+        self.invincible = 0.;
+        self.dying = Some(PLAYER_DEATH_TIME);
+        self.velocity = Velocity2d { vx: 0., vy: PLAYER_DEATH_VELOCITY };
+        self.standing = false;
+        self.climbing = false;
+        self.stop_moves();
+        self.animations.set_current("die".to_owned()).unwrap();
+    }
+
+    /*
+     * stop_moves()
+     *
+     * @brief : End the dash and let go of the ledge, so that the gravity and the knockback apply again
+     */
+    fn stop_moves(&mut self) {
+        self.dashing = 0.;
+        self.dash_tap = None;
+        self.ledge = None;
+        self.hanging = None;
+        self.ledge_climb = 0.;
+        self.ledge_cooldown = PLAYER_LEDGE_COOLDOWN;
+    }
+
+    /*
+     * is_dead()
+     *
+     * @brief : The death animation is over, the player must respawn (or the game is over without lives)
+     */
+    pub fn is_dead(&self) -> bool {
+        matches!(self.dying, Some(time) if time <= 0.)
+    }
+
+    /*
+     * revive()
+     *
+     * @brief : Full health, ready to play (before a respawn)
+     */
+    pub fn revive(&mut self) {
+        self.health = self.config.health;
+        self.invincible = 0.;
+        self.dying = None;
+        self.drop_through = 0.;
+        self.climbing = false;
+        self.jumping = false;
+        self.jump_timers = JumpTimers::default();
+        self.wall = None;
+        self.wall_time = 0.;
+        self.wall_jump_lock = 0.;
+        self.air_jump = false;
+        self.dashing = 0.;
+        self.dash_cooldown = 0.;
+        self.dash_tap = None;
+        if self.crouching {
+            self.resize(MAP_TILE_SIZE as u32);
+        }
+        self.crouching = false;
+        self.sliding = false;
+        self.room_above = true;
+        self.hanging = None;
+        self.ledge_climb = 0.;
+        self.ledge_cooldown = 0.;
+        self.breath = None;
+        self.splash = None;
+        self.animations.set_current("idle".to_owned()).unwrap();
+    }
+
+    /*
+     * resize()
+     *
+     * @brief : Change the height of the hitbox, its bottom does not move
+     */
+    fn resize(&mut self, height: u32) {
+        let offset = self.size.h as f32 - height as f32;
+        self.position.y += offset;
+        self.previous_position.y += offset;
+        self.size.h = height;
+    }
+
+    /*
+     * crouch()
+     *
+     * @brief : Smaller hitbox, the player slides if it was running
+     */
+    fn crouch(&mut self) {
+        self.resize(PLAYER_CROUCH_HEIGHT);
+        self.crouching = true;
+        self.sliding = self.velocity.vx.abs() >= PLAYER_SLIDE_MIN_SPEED;
+    }
+
+    /*
+     * stand_up()
+     *
+     * @brief : Back to the full hitbox, only when no tile is above (checked by the map at each step)
+     */
+    fn stand_up(&mut self) {
+        if self.crouching && self.room_above {
+            self.resize(MAP_TILE_SIZE as u32);
+            self.crouching = false;
+            self.sliding = false;
+        }
+    }
+
+    /*
+     * grab_ledge()
+     *
+     * @brief : Hang from a ledge, the top of the player at its corner
+     */
+    fn grab_ledge(&mut self, side: MapElementCollideType, corner: Position2d) {
+        self.position.x = if side == MapElementCollideType::Right { corner.x - self.size.w as f32 } else { corner.x };
+        self.position.y = corner.y;
+        self.velocity = Velocity2d { vx: 0., vy: 0. };
+        self.hanging = Some((side, corner));
+        self.jumping = false;
+        self.dashing = 0.;
+        self.animations.set_current("hang".to_owned()).unwrap();
+    }
+
+    /*
+     * climb_ledge()
+     *
+     * @brief : End of the climb up, the player stands on the ledge
+     */
+    fn climb_ledge(&mut self, side: MapElementCollideType, corner: Position2d) {
+        self.position.x = if side == MapElementCollideType::Right { corner.x } else { corner.x - self.size.w as f32 };
+        self.position.y = corner.y - self.size.h as f32;
+        self.hanging = None;
+        self.animations.set_current("idle".to_owned()).unwrap();
+    }
+
+    /*
+     * jump()
+     *
+     * @brief : Start a jump (lower if Up is released while going up)
+     */
+    fn jump(&mut self) {
+        self.stand_up();
+        self.velocity.vy = self.config.jump_velocity;
+        self.standing = false;
+        self.climbing = false;
+        self.jumping = true;
+        self.jump_timers = JumpTimers::default();
+        self.animations.set_current("jump".to_owned()).unwrap();
+    }
+
+    /*
+     * wall_jump()
+     *
+     * @brief : Jump away from the wall touched last, Left / Right are ignored for a while
+     */
+    fn wall_jump(&mut self) {
+        let direction = if self.wall == Some(MapElementCollideType::Right) { -1. } else { 1. };
+        self.jump();
+        self.velocity = Velocity2d { vx: direction * self.config.wall_jump_velocity.vx, vy: self.config.wall_jump_velocity.vy };
+        self.wall = None;
+        self.wall_time = 0.;
+        self.wall_jump_lock = self.config.wall_jump_lock_time;
+        self.animations.set_current("wall_jump".to_owned()).unwrap();
+    }
+
+    /*
+     * double_jump()
+     *
+     * @brief : Second jump in the air (once until the player lands, climbs or touches a wall)
+     */
+    fn double_jump(&mut self) {
+        self.jump();
+        self.velocity.vy = self.config.double_jump_velocity;
+        self.air_jump = false;
+        self.animations.set_current("double_jump".to_owned()).unwrap();
+    }
+
+    /*
+     * dash()
+     *
+     * @brief : Fast horizontal move without gravity, then a cooldown
+     */
+    fn dash(&mut self, direction: f32) {
+        self.velocity = Velocity2d { vx: direction * self.config.dash_speed, vy: 0. };
+        self.dashing = self.config.dash_time;
+        self.dash_cooldown = self.config.dash_time + self.config.dash_cooldown;
+        self.dash_tap = None;
+        self.climbing = false;
+        self.jumping = false;
+        self.animations.set_current("dash".to_owned()).unwrap();
+    }
+
+    /*
+     * set_render_alpha()
+     *
+     * @brief : Part of the next physics step already elapsed (0..1)
+     */
+    pub fn set_render_alpha(&mut self, alpha: f32) {
+        self.render_alpha = alpha;
+    }
+
+    /*
+     * get_render_position()
+     *
+     * @brief : Position to draw, between the previous and the actual physics step
+     */
+    pub fn get_render_position(&self) -> Position2d {
+        Position2d {
+            x: self.previous_position.x + (self.position.x - self.previous_position.x) * self.render_alpha,
+            y: self.previous_position.y + (self.position.y - self.previous_position.y) * self.render_alpha,
+        }
+    }
+}
+
 impl Positionable for Player {
     fn set_position(&mut self, position: Position2d) {
         self.position = position;
@@ -155,52 +846,257 @@ impl Standing for Player {
 impl Updatable for Player {
     
     fn update(&mut self, _graphics: &mut Graphics, inputs: &mut Inputs, dt: &DeltaTime) {
-        // === Friction (effet de glissage)
+        // === DEATH : no control until the respawn
+        if let Some(time) = self.dying.as_mut() {
+            *time -= dt;
+            return;
+        }
+        if self.invincible > 0. {
+            self.invincible = (self.invincible - dt).max(0.);
+        }
+
+        // Keys just pressed (for the dash)
+        let left_pressed = inputs.keyboard.is_down(&Keys::Left) && !self.left_down;
+        let right_pressed = inputs.keyboard.is_down(&Keys::Right) && !self.right_down;
+        self.left_down = inputs.keyboard.is_down(&Keys::Left);
+        self.right_down = inputs.keyboard.is_down(&Keys::Right);
+
+        // === DASH : no control until its end
+        self.dash_cooldown = (self.dash_cooldown - dt).max(0.);
+        if self.dashing > 0. {
+            self.dashing = (self.dashing - dt).max(0.);
+            self.velocity.vy = 0.;
+            if self.dashing <= 0. {
+                self.velocity.vx = self.velocity.vx.clamp(-PLAYER_MAX_SPEED, PLAYER_MAX_SPEED);
+            }
+            return;
+        }
+
+        // === LEDGE : grab it pressing toward it while falling, Up = climb up, Down = let go
+        self.ledge_cooldown = (self.ledge_cooldown - dt).max(0.);
+        if let Some((side, corner)) = self.ledge.take() {
+            let toward = if side == MapElementCollideType::Right { Keys::Right } else { Keys::Left };
+            if self.hanging.is_none() && self.ledge_cooldown <= 0. && !self.climbing && !self.crouching
+                && inputs.keyboard.is_down(&toward) {
+                self.grab_ledge(side, corner);
+            }
+        }
+        if let Some((side, corner)) = self.hanging {
+            self.velocity = Velocity2d { vx: 0., vy: 0. };
+            if self.ledge_climb > 0. {
+                self.ledge_climb -= dt;
+                if self.ledge_climb <= 0. {
+                    self.climb_ledge(side, corner);
+                }
+            }
+            else if inputs.keyboard.is_down(&Keys::Up) && self.jump_ready {
+                self.ledge_climb = PLAYER_LEDGE_CLIMB_TIME;
+                self.jump_ready = false;
+                self.animations.set_current("ledge_climb".to_owned()).unwrap();
+            }
+            else if inputs.keyboard.is_down(&Keys::Down) {
+                self.hanging = None;
+                self.ledge_cooldown = PLAYER_LEDGE_COOLDOWN;
+                self.animations.set_current("fall".to_owned()).unwrap();
+            }
+            if !inputs.keyboard.is_down(&Keys::Up) {
+                self.jump_ready = true;
+            }
+            return;
+        }
+
+        // Surface under the feet : slippery (< 1) lowers friction and acceleration,
+        // sticky (> 1) raises friction and lowers the highest speed
+        let grip = self.surface.map(|surface| surface.friction).unwrap_or(1.);
+
+        // === Friction (effet de glissage), lower when sliding
+        let friction = grip * if self.sliding { PLAYER_SLIDE_FRICTION } else { PLAYER_FRICTION };
         if self.velocity.vx > 0. {
-            self.velocity.vx -= PLAYER_FRICTION * dt;
+            self.velocity.vx -= friction * dt;
             if self.velocity.vx < 0. {
                 self.velocity.vx = 0.
             }
         }
         if self.velocity.vx < 0. {
-            self.velocity.vx += PLAYER_FRICTION * dt;
+            self.velocity.vx += friction * dt;
             if self.velocity.vx > 0. {
                 self.velocity.vx = 0.
             }
         }
 
-        // === KEYBOARD
+        // === KEYBOARD (Left / Right ignored just after a wall jump, and when sliding)
+        if self.wall_jump_lock > 0. {
+            self.wall_jump_lock = (self.wall_jump_lock - dt).max(0.);
+        }
+        let max_speed = (if self.crouching { PLAYER_CROUCH_SPEED } else { PLAYER_MAX_SPEED }) / grip.max(1.);
+        let accel = PLAYER_ACCEL * grip.min(1.);
         // Left
-        if inputs.keyboard.is_down(&Keys::Left) {
-            self.velocity.vx -= PLAYER_ACCEL * dt;
-            if self.velocity.vx < -PLAYER_MAX_SPEED {
-                self.velocity.vx = -PLAYER_MAX_SPEED; 
+        if inputs.keyboard.is_down(&Keys::Left) && self.wall_jump_lock <= 0. && !self.sliding {
+            self.velocity.vx -= accel * dt;
+            if self.velocity.vx < -max_speed {
+                self.velocity.vx = -max_speed; 
             }
             self.animations.set_current("run".to_owned()).unwrap();
         }
         // Right
-        if inputs.keyboard.is_down(&Keys::Right) {
-            self.velocity.vx += PLAYER_ACCEL * dt;
-            if self.velocity.vx > PLAYER_MAX_SPEED {
-                self.velocity.vx = PLAYER_MAX_SPEED; 
+        if inputs.keyboard.is_down(&Keys::Right) && self.wall_jump_lock <= 0. && !self.sliding {
+            self.velocity.vx += accel * dt;
+            if self.velocity.vx > max_speed {
+                self.velocity.vx = max_speed; 
             }
             self.animations.set_current("run".to_owned()).unwrap();
         }
-        // Up = JUMP
-        if inputs.keyboard.is_down(&Keys::Up) {
-            if self.standing && self.jump_ready {
-                self.velocity.vy = PLAYER_JUMP_VELOCITY * dt;
+        // Left / Right twice quickly = DASH
+        if let Some((direction, time)) = self.dash_tap {
+            self.dash_tap = if time > *dt { Some((direction, time - dt)) } else { None };
+        }
+        let tap = if left_pressed { Some(-1.) } else if right_pressed { Some(1.) } else { None };
+        if let Some(direction) = tap.filter(|_| self.abilities.dash && self.wall_jump_lock <= 0.) {
+            match self.dash_tap {
+                Some((first, _)) if first == direction && self.dash_cooldown <= 0. => {
+                    self.dash(direction);
+                    return;
+                },
+                _ => self.dash_tap = Some((direction, self.config.dash_tap_time)),
+            }
+        }
+
+        if self.drop_through > 0. {
+            self.drop_through = (self.drop_through - dt).max(0.);
+        }
+
+        // === LADDER
+        if self.climbing && (!self.on_ladder || (self.standing && inputs.keyboard.is_down(&Keys::Down))) {
+            // Walked off the ladder, or reached the ground
+            self.climbing = false;
+        }
+        else if !self.climbing && self.on_ladder && (self.standing || self.velocity.vy >= 0.)
+            && (inputs.keyboard.is_down(&Keys::Up) || (inputs.keyboard.is_down(&Keys::Down) && !self.standing)) {
+            self.climbing = true;
+            self.air_jump = true;
+            self.jump_ready = false;
+        }
+        if self.climbing {
+            let left_or_right = inputs.keyboard.is_down(&Keys::Left) || inputs.keyboard.is_down(&Keys::Right);
+
+            // Left / Right + Up = JUMP off the ladder
+            if inputs.keyboard.is_down(&Keys::Up) && left_or_right && self.jump_ready {
+                self.jump();
+                self.jump_ready = false;
+                return;
+            }
+
+            // Up / Down = CLIMB
+            self.velocity.vy = 0.;
+            if inputs.keyboard.is_down(&Keys::Up) {
+                self.velocity.vy = -PLAYER_CLIMB_SPEED;
+                self.jump_ready = false;
+            }
+            else {
+                self.jump_ready = true;
+            }
+            if inputs.keyboard.is_down(&Keys::Down) {
+                self.velocity.vy = PLAYER_CLIMB_SPEED;
+            }
+            self.animations.set_current("climb".to_owned()).unwrap();
+            return;
+        }
+
+        // === SWIM : Up = stroke, limited time under the liquid (its gravity and drag are done by the map)
+        if let Some(liquid) = self.liquid {
+            self.breath = match self.breath {
+                _ if !self.under_liquid || liquid.breath_time <= 0. => None,
+                Some(breath) => Some(breath - dt),
+                None => Some(liquid.breath_time - dt),
+            };
+            if self.breath.map(|breath| breath <= 0.).unwrap_or(false) {
+                self.hurt(MapHazard::Kill, self.position);
+                return;
+            }
+
+            if inputs.keyboard.is_down(&Keys::Up) && self.jump_ready {
+                self.velocity.vy = -liquid.stroke;
                 self.standing = false;
-                self.jump_ready = false;  
-                self.animations.set_current("jump".to_owned()).unwrap();
+                self.jump_ready = false;
+                self.animations.set_current("swim".to_owned()).unwrap();
+            }
+            else if !inputs.keyboard.is_down(&Keys::Up) {
+                self.jump_ready = true;
+            }
+            self.stand_up();
+            return;
+        }
+        self.breath = None;
+
+        // === CROUCH : Down on the ground (slide when running), stand up only with room above
+        if inputs.keyboard.is_down(&Keys::Down) && self.standing && !self.crouching {
+            self.crouch();
+        }
+        else if self.crouching && (!inputs.keyboard.is_down(&Keys::Down) || !self.standing) {
+            self.stand_up();
+        }
+        if self.sliding && self.velocity.vx.abs() < PLAYER_CROUCH_SPEED {
+            self.sliding = false;
+        }
+        if self.crouching {
+            self.animations.set_current((if self.sliding { "slide" } else { "crouch" }).to_owned()).unwrap();
+        }
+
+        // Down + Up = DROP THROUGH a one-way platform
+        if inputs.keyboard.is_down(&Keys::Down) && inputs.keyboard.is_down(&Keys::Up)
+            && self.standing && self.on_one_way && self.jump_ready {
+            self.drop_through = PLAYER_DROP_THROUGH_TIME;
+            self.standing = false;
+            self.jump_timers.coyote = 0.;
+            self.jump_ready = false;
+            self.animations.set_current("fall".to_owned()).unwrap();
+        }
+        // Second jump available again on the ground or against a wall
+        if self.standing || self.on_wall.is_some() {
+            self.air_jump = true;
+        }
+
+        // === WALL : slide down slowly pressing into it (remembered a little for the wall jump)
+        if self.on_wall.is_some() && !self.standing {
+            self.wall = self.on_wall;
+            self.wall_time = self.config.coyote_time;
+            if self.velocity.vy > self.config.wall_slide_speed {
+                self.velocity.vy = self.config.wall_slide_speed;
+                self.animations.set_current("wall_slide".to_owned()).unwrap();
             }
         }
-        else if self.jump_ready == false {
+        else {
+            self.wall_time = (self.wall_time - dt).max(0.);
+        }
+
+        // Up = JUMP (pressed a little before landing, or a little after leaving the ground or a wall)
+        let up = inputs.keyboard.is_down(&Keys::Up);
+        self.jump_timers.update(&self.config, self.standing, up && self.jump_ready, *dt);
+        if up && self.jump_ready {
+            self.jump_ready = false;
+        }
+        if self.jump_timers.can_jump(self.standing) {
+            self.jump();
+        }
+        else if self.jump_timers.buffer > 0. && self.wall_time > 0. {
+            self.wall_jump();
+        }
+        else if self.jump_timers.buffer > 0. && self.abilities.double_jump && self.air_jump {
+            self.double_jump();
+        }
+        self.jump_timers.end_step(*dt);
+
+        // Up released while going up = lower jump
+        if !up {
             self.jump_ready = true;
+            self.velocity.vy = jump_cut(&self.config, self.velocity.vy, self.jumping);
+            self.jumping = false;
+        }
+        if self.velocity.vy >= 0. {
+            self.jumping = false;
         }
 
-        // === MOVE
-        self.position = self.position + self.velocity;
+        // === MOVE : done by the collision resolution of the map
     }
 }
 
@@ -214,4 +1110,66 @@ impl WithSize for Player {
     fn get_size(&self) -> &Size2d {
         &self.size
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1. / 120.;
+
+    // Timers after leaving the ground (or pressing in the air) and waiting for a time
+    fn wait(timers: &mut JumpTimers, config: &PlayerConfig, standing: bool, time: f32) {
+        for _ in 0..(time / DT).round() as u32 {
+            timers.update(config, standing, false, DT);
+            timers.end_step(DT);
+        }
+    }
+
+    #[test]
+    fn jump_within_the_coyote_time() {
+        let config = PlayerConfig::default();
+        let jump_after = |time: f32| {
+            let mut timers = JumpTimers::default();
+            timers.update(&config, true, false, DT);
+            wait(&mut timers, &config, false, time);
+            timers.update(&config, false, true, DT);
+            timers.can_jump(false)
+        };
+
+        assert!(jump_after(0.));
+        assert!(jump_after(config.coyote_time - 2. * DT));
+        assert!(!jump_after(config.coyote_time));
+        assert!(!jump_after(config.coyote_time + 2. * DT));
+    }
+
+    #[test]
+    fn jump_pressed_before_landing() {
+        let config = PlayerConfig::default();
+        let land_after = |time: f32| {
+            let mut timers = JumpTimers::default();
+            timers.update(&config, false, true, DT);
+            timers.end_step(DT);
+            wait(&mut timers, &config, false, time);
+            timers.update(&config, true, false, DT);
+            timers.can_jump(true)
+        };
+
+        assert!(land_after(0.));
+        assert!(land_after(config.jump_buffer_time - 2. * DT));
+        assert!(!land_after(config.jump_buffer_time));
+        // Nothing pressed : no jump on landing
+        let mut timers = JumpTimers::default();
+        timers.update(&config, true, false, DT);
+        assert!(!timers.can_jump(true));
+    }
+
+    #[test]
+    fn releasing_up_cuts_the_jump() {
+        let config = PlayerConfig::default();
+
+        assert_eq!(jump_cut(&config, -400., true), -400. * config.jump_cut);
+        // Falling, or going up without a jump (knockback) : unchanged
+        assert_eq!(jump_cut(&config, 100., true), 100.);
+        assert_eq!(jump_cut(&config, -400., false), -400.);
+    }
+}
@@ -0,0 +1,52 @@
+use game2d::game::common::{Movable, Position, Position2d, Positionable, Sizable, Size2d, Standing, Velocity, Velocity2d, WithPosition, WithSize};
+
+use crate::SpriteCommonPlaterformerTrait;
+
+// ################################################################################################################
+// #                                             T E S T   B O D Y                                                #
+// ################################################################################################################
+// Box only moved by update_sprite (no inputs, no graphics), for the tests of the map and of the physics
+pub struct Body {
+    pub position: Position2d,
+    pub velocity: Velocity2d,
+    pub size: Size2d,
+    pub standing: bool,
+}
+
+impl Body {
+    /*
+     * new()
+     *
+     * @brief : Body not moving (and not standing) at a position
+     */
+    pub fn new(position: Position2d, size: Size2d) -> Self {
+        Self { position, velocity: Velocity2d { vx: 0., vy: 0. }, size, standing: false }
+    }
+}
+
+impl WithPosition for Body {
+    fn get_position(&self) -> &Position2d { &self.position }
+}
+impl Positionable for Body {
+    fn set_position(&mut self, position: Position2d) { self.position = position; }
+    fn set_x(&mut self, x: Position) { self.position.x = x; }
+    fn set_y(&mut self, y: Position) { self.position.y = y; }
+}
+impl WithSize for Body {
+    fn get_size(&self) -> &Size2d { &self.size }
+}
+impl Sizable for Body {
+    fn set_size(&mut self, size: Size2d) { self.size = size; }
+}
+impl Movable for Body {
+    fn get_velocity(&self) -> &Velocity2d { &self.velocity }
+    fn get_mut_velocity(&mut self) -> &mut Velocity2d { &mut self.velocity }
+    fn set_velocity(&mut self, velocity: Velocity2d) { self.velocity = velocity; }
+    fn set_vx(&mut self, vx: Velocity) { self.velocity.vx = vx; }
+    fn set_vy(&mut self, vy: Velocity) { self.velocity.vy = vy; }
+}
+impl Standing for Body {
+    fn get_standing(&self) -> bool { self.standing }
+    fn set_standing(&mut self, standing: bool) { self.standing = standing; }
+}
+impl SpriteCommonPlaterformerTrait for Body {}
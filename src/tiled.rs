@@ -0,0 +1,724 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use base64::Engine;
+use game2d::game::common::Position2d;
+use serde::Deserialize;
+
+use crate::level::{MapCoord, MapElement, MapHazard, MapLevel, MapLiquid, MapPowerUp, MapSurface, MAP_EMPTY, MAP_PLAYER_START};
+
+// Flags stored by Tiled in the high bits of a gid (flip / rotation)
+const TILED_GID_FLAGS: u32 = 0xF000_0000;
+// First char given to tiles without an "id" property (unicode private use area)
+const TILED_FIRST_CHAR: u32 = 0xE000;
+
+pub const TILED_PLAYER_START: &str = "player_start";
+
+// ################################################################################################################
+// #                                           T I L E D   L E V E L                                              #
+// ################################################################################################################
+//
+// Supported : orthogonal, finite maps with embedded "collection of images" tilesets.
+// Tile layers (CSV, base64 uncompressed or XML) are merged from bottom to top : the upper
+// non empty tile is drawn, and keeps the gameplay properties of the tiles below it does not
+// have (decoration over a wall, lava or a ladder), unless it is solid. Per tile properties :
+//   - solid (bool)          : MapElement::solid
+//   - one_way (bool)        : MapElement::one_way
+//   - slope (string)        : MapElement::slope, "left,right" heights (0..1)
+//   - climbable (bool)      : MapElement::climbable
+//   - hazard (string)       : MapElement::hazard, "hurt" or "kill"
+//   - damage (int)          : health points lost by a "hurt" hazard (default 1)
+//   - checkpoint (bool)     : MapElement::checkpoint
+//   - active_image (string) : MapElement::active_filename (required by a checkpoint)
+//   - power_up (string)     : MapElement::power_up, "double_jump" or "dash"
+//   - liquid (bool)         : MapElement::liquid, with the optional float properties
+//                             gravity_scale, drag, stroke and breath_time (see MapLiquid)
+//   - friction (float)      : MapElement::surface, with the optional float properties
+//                             conveyor and bounce (see MapSurface)
+//   - exit (bool)           : MapElement::exit
+//   - name (string)         : MapElement::name (default "<tileset>:<id>")
+//   - id (string)           : char used for this tile (default : private char)
+//   - others                : copied to MapElement::properties
+// The object named (or typed) "player_start" gives the start of the player (one per map).
+//
+pub struct TiledLevel {
+    pub elements: HashMap<char, MapElement>,
+    pub level: MapLevel,
+    pub player_start: Position2d,
+}
+
+struct TiledDocument {
+    orientation: String,
+    infinite: bool,
+    width: usize,
+    height: usize,
+    tile_width: u32,
+    tile_height: u32,
+    tilesets: Vec<TiledTileset>,
+    layers: Vec<TiledLayer>,
+    objects: Vec<TiledObject>,
+}
+
+struct TiledTileset {
+    first_gid: u32,
+    name: String,
+    tiles: Vec<TiledTile>,
+}
+
+struct TiledTile {
+    id: u32,
+    image: String,
+    properties: HashMap<String, String>,
+}
+
+struct TiledLayer {
+    name: String,
+    data: Vec<u32>,
+}
+
+struct TiledObject {
+    name: String,
+    class: String,
+    x: f32,
+    y: f32,
+}
+
+/*
+ * load_tiled()
+ *
+ * @brief: Import a Tiled map (.tmx or .tmj)
+ */
+pub fn load_tiled(filename: &str) -> Result<TiledLevel, String> {
+    let content = fs::read_to_string(filename)
+        .map_err(|e| format!("Tiled : unable to read {} ({})", filename, e))?;
+
+    let path = Path::new(filename);
+    let document = match path.extension().and_then(|e| e.to_str()) {
+        Some("tmx") => parse_tmx(&content),
+        Some("tmj") | Some("json") => parse_tmj(&content),
+        _ => Err("unknown extension (expected .tmx or .tmj)".to_string()),
+    };
+
+    document
+        .and_then(|document| build_level(document, path.parent().unwrap_or(Path::new(""))))
+        .map_err(|e| format!("Tiled : {} in {}", e, filename))
+}
+
+/*
+ * build_level()
+ *
+ * @brief: Convert a Tiled document to the elements / level of a Map
+ */
+fn build_level(document: TiledDocument, directory: &Path) -> Result<TiledLevel, String> {
+    if document.orientation != "orthogonal" {
+        return Err(format!("orientation {} not supported", document.orientation));
+    }
+    if document.infinite {
+        return Err("infinite maps are not supported".to_string());
+    }
+
+    // Elements : one char per tile
+    let mut elements = HashMap::new();
+    let mut chars: HashMap<u32, char> = HashMap::new();
+    let mut next_char = TILED_FIRST_CHAR;
+
+    for tileset in document.tilesets.iter() {
+        for tile in tileset.tiles.iter() {
+            let mut properties = tile.properties.clone();
+
+            let id = match properties.remove("id") {
+                Some(id) => {
+                    let mut id_chars = id.chars();
+                    match (id_chars.next(), id_chars.next()) {
+                        (Some(c), None) if c != MAP_EMPTY && c != MAP_PLAYER_START => c,
+                        _ => return Err(format!("tile {} of {} : invalid id \"{}\"", tile.id, tileset.name, id)),
+                    }
+                },
+                None => {
+                    let c = char::from_u32(next_char).ok_or("too many tiles")?;
+                    next_char += 1;
+                    c
+                }
+            };
+            if elements.contains_key(&id) {
+                return Err(format!("tile {} of {} : id '{}' already used", tile.id, tileset.name, id));
+            }
+
+            let filename = directory.join(&tile.image).to_string_lossy().to_string();
+            if !Path::new(&filename).is_file() {
+                return Err(format!("tile {} of {} : image {} not found", tile.id, tileset.name, filename));
+            }
+
+            let name = properties.remove("name").unwrap_or(format!("{}:{}", tileset.name, tile.id));
+            let solid = properties.remove("solid").map(|s| s == "true").unwrap_or(false);
+            let one_way = properties.remove("one_way").map(|s| s == "true").unwrap_or(false);
+            let climbable = properties.remove("climbable").map(|s| s == "true").unwrap_or(false);
+            let damage = match properties.remove("damage") {
+                Some(damage) => damage.parse::<u32>().map_err(|_| format!("tile {} of {} : invalid damage \"{}\"", tile.id, tileset.name, damage))?,
+                None => 1,
+            };
+            let hazard = match properties.remove("hazard") {
+                Some(hazard) => Some(MapHazard::from_name(&hazard, damage).ok_or(format!("tile {} of {} : invalid hazard \"{}\"", tile.id, tileset.name, hazard))?),
+                None => None,
+            };
+            let checkpoint = properties.remove("checkpoint").map(|s| s == "true").unwrap_or(false);
+            let active_filename = properties.remove("active_image").map(|image| directory.join(image).to_string_lossy().to_string());
+            match &active_filename {
+                Some(filename) if !checkpoint => return Err(format!("tile {} of {} : active_image {} without checkpoint", tile.id, tileset.name, filename)),
+                Some(filename) if !Path::new(filename).is_file() => return Err(format!("tile {} of {} : active image {} not found", tile.id, tileset.name, filename)),
+                None if checkpoint => return Err(format!("tile {} of {} : checkpoint without active_image", tile.id, tileset.name)),
+                _ => {},
+            }
+            let power_up = match properties.remove("power_up") {
+                Some(power_up) => Some(MapPowerUp::from_name(&power_up).ok_or(format!("tile {} of {} : invalid power_up \"{}\"", tile.id, tileset.name, power_up))?),
+                None => None,
+            };
+            let liquid = if properties.remove("liquid").map(|s| s == "true").unwrap_or(false) {
+                let mut liquid = MapLiquid::default();
+                for (name, value) in [("gravity_scale", &mut liquid.gravity_scale), ("drag", &mut liquid.drag),
+                                      ("stroke", &mut liquid.stroke), ("breath_time", &mut liquid.breath_time)] {
+                    if let Some(property) = properties.remove(name) {
+                        *value = property.parse::<f32>().map_err(|_| format!("tile {} of {} : invalid {} \"{}\"", tile.id, tileset.name, name, property))?;
+                    }
+                }
+                Some(liquid)
+            } else {
+                None
+            };
+            let mut surface = MapSurface::default();
+            let mut has_surface = false;
+            for (name, value) in [("friction", &mut surface.friction), ("conveyor", &mut surface.conveyor), ("bounce", &mut surface.bounce)] {
+                if let Some(property) = properties.remove(name) {
+                    *value = property.parse::<f32>().map_err(|_| format!("tile {} of {} : invalid {} \"{}\"", tile.id, tileset.name, name, property))?;
+                    has_surface = true;
+                }
+            }
+            let surface = if has_surface { Some(surface) } else { None };
+            let exit = properties.remove("exit").map(|s| s == "true").unwrap_or(false);
+            let slope = match properties.remove("slope") {
+                Some(slope) => Some(parse_slope(&slope).ok_or(format!("tile {} of {} : invalid slope \"{}\"", tile.id, tileset.name, slope))?),
+                None => None,
+            };
+
+            let element = MapElement { name, filename, solid, one_way, slope, climbable, hazard, checkpoint, active_filename, power_up, liquid, surface, exit, properties };
+            element.validate().map_err(|e| format!("tile {} of {} : {}", tile.id, tileset.name, e))?;
+
+            chars.insert(tileset.first_gid + tile.id, id);
+            elements.insert(id, element);
+        }
+    }
+
+    // Level : layers merged from bottom to top
+    let mut level: MapLevel = vec![vec![MAP_EMPTY; document.width]; document.height];
+    let mut merged: HashMap<(char, char), char> = HashMap::new();
+
+    for layer in document.layers.iter() {
+        if layer.data.len() != document.width * document.height {
+            return Err(format!("layer {} has {} tiles, expected {}", layer.name, layer.data.len(), document.width * document.height));
+        }
+        for (index, gid) in layer.data.iter().enumerate() {
+            let gid = gid & !TILED_GID_FLAGS;
+            if gid == 0 {
+                continue;
+            }
+            let c = *chars.get(&gid).ok_or(format!("layer {} : unknown tile gid {}", layer.name, gid))?;
+            let cell = &mut level[index / document.width][index % document.width];
+            *cell = merge_tiles(&mut elements, &mut merged, &mut next_char, c, *cell)?;
+        }
+    }
+
+    // Player start (only one)
+    let starts: Vec<&TiledObject> = document.objects.iter()
+        .filter(|o| o.name == TILED_PLAYER_START || o.class == TILED_PLAYER_START)
+        .collect();
+    let player_start = match starts[..] {
+        [start] => {
+            let col = (start.x / document.tile_width as f32).floor() as isize;
+            let lig = (start.y / document.tile_height as f32).floor() as isize;
+            MapCoord::to_position2d(MapCoord { lig, col })
+        },
+        [] => return Err(format!("no object {}", TILED_PLAYER_START)),
+        _ => return Err(format!("{} objects {} (only one expected)", starts.len(), TILED_PLAYER_START)),
+    };
+
+    Ok(TiledLevel { elements, level, player_start })
+}
+
+/*
+ * merge_tiles()
+ *
+ * @brief: Tile of a cell once an upper tile is put over a lower one : the upper tile, or a new element
+ *         (created once per pair) with its image and the gameplay of the lower tile it does not have
+ *         (a solid upper tile hides everything below it, the surface goes with the collision)
+ */
+fn merge_tiles(elements: &mut HashMap<char, MapElement>, merged: &mut HashMap<(char, char), char>, next_char: &mut u32, upper: char, lower: char) -> Result<char, String> {
+    if lower == MAP_EMPTY {
+        return Ok(upper);
+    }
+    let collides = |element: &MapElement| element.solid || element.one_way || element.slope.is_some();
+    let (upper_element, lower_element) = (&elements[&upper], &elements[&lower]);
+    let inherits = (!collides(upper_element) && collides(lower_element))
+        || (!upper_element.climbable && lower_element.climbable)
+        || (upper_element.hazard.is_none() && lower_element.hazard.is_some())
+        || (!upper_element.checkpoint && lower_element.checkpoint)
+        || (upper_element.power_up.is_none() && lower_element.power_up.is_some())
+        || (upper_element.liquid.is_none() && lower_element.liquid.is_some())
+        || (!upper_element.exit && lower_element.exit);
+    if upper_element.solid || !inherits {
+        return Ok(upper);
+    }
+    if let Some(c) = merged.get(&(upper, lower)) {
+        return Ok(*c);
+    }
+
+    let c = char::from_u32(*next_char).ok_or("too many tiles")?;
+    *next_char += 1;
+
+    let collision = if collides(upper_element) { upper_element } else { lower_element };
+    let checkpoint = if upper_element.checkpoint { upper_element } else { lower_element };
+    let mut properties = lower_element.properties.clone();
+    properties.extend(upper_element.properties.clone());
+    let element = MapElement {
+        name: format!("{}+{}", upper_element.name, lower_element.name),
+        solid: collision.solid,
+        one_way: collision.one_way,
+        slope: collision.slope,
+        surface: collision.surface,
+        climbable: upper_element.climbable || lower_element.climbable,
+        hazard: upper_element.hazard.or(lower_element.hazard),
+        checkpoint: checkpoint.checkpoint,
+        active_filename: checkpoint.active_filename.clone(),
+        power_up: upper_element.power_up.or(lower_element.power_up),
+        liquid: upper_element.liquid.or(lower_element.liquid),
+        exit: upper_element.exit || lower_element.exit,
+        properties,
+        ..upper_element.clone()
+    };
+    element.validate().map_err(|e| format!("{} : {}", element.name, e))?;
+    elements.insert(c, element);
+    merged.insert((upper, lower), c);
+
+    Ok(c)
+}
+
+fn parse_slope(slope: &str) -> Option<[f32; 2]> {
+    let heights: Vec<f32> = slope.split(',').map(|h| h.trim().parse::<f32>().ok()).collect::<Option<_>>()?;
+    match heights[..] {
+        [left, right] if (0. ..=1.).contains(&left) && (0. ..=1.).contains(&right) => Some([left, right]),
+        _ => None,
+    }
+}
+
+// ################################################################################################################
+// #                                          D A T A   D E C O D I N G                                           #
+// ################################################################################################################
+fn decode_csv(data: &str) -> Result<Vec<u32>, String> {
+    data.split(',')
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse::<u32>().map_err(|_| format!("invalid gid \"{}\"", v)))
+        .collect()
+}
+
+fn decode_base64(data: &str, compression: Option<&str>) -> Result<Vec<u32>, String> {
+    if let Some(compression) = compression.filter(|c| !c.is_empty()) {
+        return Err(format!("compression {} not supported", compression));
+    }
+
+    let data: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+    let bytes = base64::engine::general_purpose::STANDARD.decode(data).map_err(|e| e.to_string())?;
+    if bytes.len() % 4 != 0 {
+        return Err("base64 data is not a list of gids".to_string());
+    }
+
+    Ok(bytes.chunks_exact(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+}
+
+// ################################################################################################################
+// #                                                  T M X                                                       #
+// ################################################################################################################
+fn parse_tmx(content: &str) -> Result<TiledDocument, String> {
+    let xml = roxmltree::Document::parse(content).map_err(|e| e.to_string())?;
+    let map = xml.root_element();
+    if !map.has_tag_name("map") {
+        return Err("root element is not <map>".to_string());
+    }
+
+    let mut document = TiledDocument {
+        orientation: map.attribute("orientation").unwrap_or("orthogonal").to_string(),
+        infinite: map.attribute("infinite") == Some("1"),
+        width: tmx_attribute(map, "width")?,
+        height: tmx_attribute(map, "height")?,
+        tile_width: tmx_attribute(map, "tilewidth")?,
+        tile_height: tmx_attribute(map, "tileheight")?,
+        tilesets: Vec::new(),
+        layers: Vec::new(),
+        objects: Vec::new(),
+    };
+
+    for tileset in map.children().filter(|n| n.has_tag_name("tileset")) {
+        document.tilesets.push(parse_tmx_tileset(tileset)?);
+    }
+    parse_tmx_layers(map, &mut document)?;
+
+    Ok(document)
+}
+
+fn tmx_attribute<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> Result<T, String> {
+    node.attribute(name)
+        .ok_or(format!("<{}> without {}", node.tag_name().name(), name))?
+        .parse::<T>()
+        .map_err(|_| format!("<{}> : invalid {}", node.tag_name().name(), name))
+}
+
+fn parse_tmx_tileset(node: roxmltree::Node) -> Result<TiledTileset, String> {
+    let name = node.attribute("name").unwrap_or_default().to_string();
+    if let Some(source) = node.attribute("source") {
+        return Err(format!("external tileset {} not supported (embed it in the map)", source));
+    }
+    if node.children().any(|n| n.has_tag_name("image")) {
+        return Err(format!("tileset {} : only \"collection of images\" tilesets are supported", name));
+    }
+
+    let mut tiles = Vec::new();
+    for tile in node.children().filter(|n| n.has_tag_name("tile")) {
+        let image = tile.children()
+            .find(|n| n.has_tag_name("image"))
+            .and_then(|n| n.attribute("source"))
+            .ok_or(format!("tileset {} : tile without image", name))?;
+
+        let mut properties = HashMap::new();
+        for property in tile.descendants().filter(|n| n.has_tag_name("property")) {
+            let value = property.attribute("value").or(property.text()).unwrap_or_default();
+            properties.insert(property.attribute("name").unwrap_or_default().to_string(), value.to_string());
+        }
+
+        tiles.push(TiledTile { id: tmx_attribute(tile, "id")?, image: image.to_string(), properties });
+    }
+
+    Ok(TiledTileset { first_gid: tmx_attribute(node, "firstgid")?, name, tiles })
+}
+
+fn parse_tmx_layers(node: roxmltree::Node, document: &mut TiledDocument) -> Result<(), String> {
+    for child in node.children().filter(|n| n.attribute("visible") != Some("0")) {
+        match child.tag_name().name() {
+            "layer" => {
+                let name = child.attribute("name").unwrap_or_default().to_string();
+                let data = child.children()
+                    .find(|n| n.has_tag_name("data"))
+                    .ok_or(format!("layer {} without data", name))?;
+                let text = data.text().unwrap_or_default();
+
+                let data = match data.attribute("encoding") {
+                    Some("csv") => decode_csv(text),
+                    Some("base64") => decode_base64(text, data.attribute("compression")),
+                    Some(encoding) => Err(format!("encoding {} not supported", encoding)),
+                    None => data.children()
+                        .filter(|n| n.has_tag_name("tile"))
+                        .map(|n| {
+                            let gid = n.attribute("gid").unwrap_or("0");
+                            gid.parse::<u32>().map_err(|_| format!("invalid gid \"{}\"", gid))
+                        })
+                        .collect(),
+                }.map_err(|e| format!("layer {} : {}", name, e))?;
+
+                document.layers.push(TiledLayer { name, data });
+            },
+            "objectgroup" => {
+                for object in child.children().filter(|n| n.has_tag_name("object")) {
+                    document.objects.push(TiledObject {
+                        name: object.attribute("name").unwrap_or_default().to_string(),
+                        class: object.attribute("type").or(object.attribute("class")).unwrap_or_default().to_string(),
+                        x: tmx_attribute(object, "x")?,
+                        y: tmx_attribute(object, "y")?,
+                    });
+                }
+            },
+            "group" => parse_tmx_layers(child, document)?,
+            _ => {},
+        }
+    }
+
+    Ok(())
+}
+
+// ################################################################################################################
+// #                                                  T M J                                                       #
+// ################################################################################################################
+#[derive(Deserialize)]
+struct TmjMap {
+    #[serde(default)]
+    orientation: String,
+    #[serde(default)]
+    infinite: bool,
+    width: usize,
+    height: usize,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default)]
+    tilesets: Vec<TmjTileset>,
+    #[serde(default)]
+    layers: Vec<TmjLayer>,
+}
+
+#[derive(Deserialize)]
+struct TmjTileset {
+    firstgid: u32,
+    #[serde(default)]
+    name: String,
+    source: Option<String>,
+    image: Option<String>,
+    #[serde(default)]
+    tiles: Vec<TmjTile>,
+}
+
+#[derive(Deserialize)]
+struct TmjTile {
+    id: u32,
+    image: Option<String>,
+    #[serde(default)]
+    properties: Vec<TmjProperty>,
+}
+
+#[derive(Deserialize)]
+struct TmjProperty {
+    name: String,
+    value: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct TmjLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default = "tmj_visible")]
+    visible: bool,
+    data: Option<serde_json::Value>,
+    encoding: Option<String>,
+    compression: Option<String>,
+    #[serde(default)]
+    objects: Vec<TmjObject>,
+    #[serde(default)]
+    layers: Vec<TmjLayer>,
+}
+
+#[derive(Deserialize)]
+struct TmjObject {
+    #[serde(default)]
+    name: String,
+    #[serde(default, rename = "type")]
+    kind: String,
+    #[serde(default)]
+    class: String,
+    x: f32,
+    y: f32,
+}
+
+fn tmj_visible() -> bool {
+    true
+}
+
+fn parse_tmj(content: &str) -> Result<TiledDocument, String> {
+    let map: TmjMap = serde_json::from_str(content).map_err(|e| e.to_string())?;
+
+    let mut document = TiledDocument {
+        orientation: if map.orientation.is_empty() { "orthogonal".to_string() } else { map.orientation },
+        infinite: map.infinite,
+        width: map.width,
+        height: map.height,
+        tile_width: map.tilewidth,
+        tile_height: map.tileheight,
+        tilesets: Vec::new(),
+        layers: Vec::new(),
+        objects: Vec::new(),
+    };
+
+    for tileset in map.tilesets {
+        if let Some(source) = tileset.source {
+            return Err(format!("external tileset {} not supported (embed it in the map)", source));
+        }
+        if tileset.image.is_some() {
+            return Err(format!("tileset {} : only \"collection of images\" tilesets are supported", tileset.name));
+        }
+
+        let mut tiles = Vec::new();
+        for tile in tileset.tiles {
+            let image = tile.image.ok_or(format!("tileset {} : tile without image", tileset.name))?;
+            let properties = tile.properties.into_iter()
+                .map(|p| {
+                    let value = match p.value {
+                        serde_json::Value::String(s) => s,
+                        value => value.to_string(),
+                    };
+                    (p.name, value)
+                })
+                .collect();
+            tiles.push(TiledTile { id: tile.id, image, properties });
+        }
+
+        document.tilesets.push(TiledTileset { first_gid: tileset.firstgid, name: tileset.name, tiles });
+    }
+    parse_tmj_layers(map.layers, &mut document)?;
+
+    Ok(document)
+}
+
+fn parse_tmj_layers(layers: Vec<TmjLayer>, document: &mut TiledDocument) -> Result<(), String> {
+    for layer in layers.into_iter().filter(|l| l.visible) {
+        match layer.kind.as_str() {
+            "tilelayer" => {
+                let data = match (layer.data, layer.encoding.as_deref()) {
+                    (Some(serde_json::Value::Array(gids)), _) => gids.iter()
+                        .map(|g| g.as_u64().map(|g| g as u32).ok_or(format!("invalid gid {}", g)))
+                        .collect(),
+                    (Some(serde_json::Value::String(data)), Some("base64")) => decode_base64(&data, layer.compression.as_deref()),
+                    _ => Err("data not supported (expected csv or base64)".to_string()),
+                }.map_err(|e| format!("layer {} : {}", layer.name, e))?;
+
+                document.layers.push(TiledLayer { name: layer.name, data });
+            },
+            "objectgroup" => {
+                for object in layer.objects {
+                    let class = if object.kind.is_empty() { object.class } else { object.kind };
+                    document.objects.push(TiledObject { name: object.name, class, x: object.x, y: object.y });
+                }
+            },
+            "group" => parse_tmj_layers(layer.layers, document)?,
+            _ => {},
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Map of width x 1 tiles, with the tiles (gid 1, 2...) of one tileset, images in images/
+    fn document(width: usize, tiles: Vec<(&str, Vec<(&str, &str)>)>, layers: Vec<Vec<u32>>) -> TiledDocument {
+        TiledDocument {
+            orientation: "orthogonal".to_string(),
+            infinite: false,
+            width,
+            height: 1,
+            tile_width: 32,
+            tile_height: 32,
+            tilesets: vec![TiledTileset {
+                first_gid: 1,
+                name: "test".to_string(),
+                tiles: tiles.into_iter().enumerate().map(|(id, (image, properties))| TiledTile {
+                    id: id as u32,
+                    image: image.to_string(),
+                    properties: properties.into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+                }).collect(),
+            }],
+            layers: layers.into_iter().enumerate().map(|(i, data)| TiledLayer { name: format!("layer{}", i), data }).collect(),
+            objects: vec![TiledObject { name: TILED_PLAYER_START.to_string(), class: String::new(), x: 0., y: 0. }],
+        }
+    }
+
+    #[test]
+    fn decoration_keeps_the_collision_below() {
+        let tiles = vec![
+            ("tile1.png", vec![("id", "1"), ("solid", "true")]),
+            ("platform.png", vec![("id", "-"), ("one_way", "true")]),
+            ("ladder.png", vec![("id", "H")]),
+        ];
+        let layers = vec![vec![1, 2, 0, 1], vec![3, 3, 3, 0]];
+        let level = build_level(document(4, tiles, layers), Path::new("images")).unwrap();
+
+        let row = &level.level[0];
+        let wall = &level.elements[&row[0]];
+        assert!(wall.solid && wall.filename.ends_with("ladder.png"));
+        let platform = &level.elements[&row[1]];
+        assert!(platform.one_way && !platform.solid && platform.filename.ends_with("ladder.png"));
+        assert_eq!(row[2], 'H');
+        assert_eq!(row[3], '1');
+        // One new element per pair of tiles
+        assert_eq!(level.elements.len(), 5);
+    }
+
+    #[test]
+    fn tiles_are_validated_like_the_tileset() {
+        let build = |properties: Vec<(&str, &str)>| build_level(document(1, vec![("tile1.png", properties)], vec![vec![1]]), Path::new("images")).err();
+
+        assert_eq!(build(vec![("slope", "0,1")]), None);
+        assert_eq!(build(vec![("slope", "0,1"), ("solid", "true")]).unwrap(), "tile 0 of test : slope must be heights between 0 and 1, on a tile not solid");
+        assert_eq!(build(vec![("liquid", "true"), ("drag", "2")]), None);
+        assert_eq!(build(vec![("liquid", "true"), ("solid", "true")]).unwrap(), "tile 0 of test : liquid must have drag, stroke and breath time >= 0, on a tile not solid");
+        assert!(build(vec![("liquid", "true"), ("drag", "-1")]).is_some());
+        assert!(build(vec![("liquid", "true"), ("breath_time", "-1")]).is_some());
+        assert_eq!(build(vec![("solid", "true"), ("friction", "0.1")]), None);
+        assert_eq!(build(vec![("conveyor", "50")]).unwrap(), "tile 0 of test : surface must have friction > 0 and bounce between 0 and 1, on a solid or one-way tile");
+        assert!(build(vec![("one_way", "true"), ("friction", "0")]).is_some());
+        assert!(build(vec![("solid", "true"), ("bounce", "1.5")]).is_some());
+    }
+
+    #[test]
+    fn decoration_keeps_the_gameplay_below() {
+        let tiles = vec![
+            ("lava.png", vec![("id", "~"), ("hazard", "kill")]),
+            ("ladder.png", vec![("id", "H"), ("climbable", "true")]),
+            ("exit.png", vec![("id", "E"), ("exit", "true"), ("colour", "red")]),
+            ("tile1.png", vec![("id", "1"), ("solid", "true")]),
+            ("platform.png", vec![("id", "d"), ("colour", "green")]),
+            ("ice.png", vec![("id", "i"), ("solid", "true"), ("friction", "0.1")]),
+        ];
+        let layers = vec![vec![1, 2, 3, 1, 6], vec![5, 5, 5, 4, 5]];
+        let level = build_level(document(5, tiles, layers), Path::new("images")).unwrap();
+
+        let row = &level.level[0];
+        let lava = &level.elements[&row[0]];
+        assert!(lava.hazard == Some(MapHazard::Kill) && lava.filename.ends_with("platform.png"));
+        assert!(level.elements[&row[1]].climbable);
+        let exit = &level.elements[&row[2]];
+        assert!(exit.exit && exit.properties["colour"] == "green");
+        // A solid tile hides the lava
+        assert_eq!(row[3], '1');
+        // The ice stays slippery
+        let ice = &level.elements[&row[4]];
+        assert!(ice.solid && ice.filename.ends_with("platform.png"));
+        assert_eq!(ice.surface.map(|surface| surface.friction), Some(0.1));
+    }
+
+    #[test]
+    fn load_csv_base64_and_json_maps() {
+        for filename in ["levels/tests/csv.tmx", "levels/tests/base64.tmx", "levels/tests/map.tmj"] {
+            let level = load_tiled(filename).unwrap();
+
+            assert_eq!(level.level.len(), 3, "{}", filename);
+            assert_eq!(level.level[1], vec![MAP_EMPTY; 4], "{}", filename);
+            assert_eq!(level.player_start, Position2d { x: 32., y: 32. }, "{}", filename);
+            // Vines over a wall and over lava
+            let row = &level.level[2];
+            let wall = &level.elements[&row[0]];
+            assert!(wall.solid && wall.filename.ends_with("ladder.png"), "{}", filename);
+            let lava = &level.elements[&row[1]];
+            assert!(!lava.solid && lava.hazard == Some(MapHazard::Kill) && lava.filename.ends_with("ladder.png"), "{}", filename);
+            assert_eq!(&row[2..], ['1', '1'], "{}", filename);
+        }
+    }
+
+    #[test]
+    fn decode_layer_data() {
+        assert_eq!(decode_csv("\n1,2,0,\n3,0,1\n").unwrap(), vec![1, 2, 0, 3, 0, 1]);
+        assert_eq!(decode_csv("1,x").unwrap_err(), "invalid gid \"x\"");
+
+        // 1, 2 and 3 flipped horizontally (flag kept, removed by build_level)
+        assert_eq!(decode_base64(" AQAAAAIAAAA\nDAACA ", None).unwrap(), vec![1, 2, 0x8000_0003]);
+        assert_eq!(decode_base64("AQAAAA==", Some("")).unwrap(), vec![1]);
+        assert!(decode_base64("AQAAAA==", Some("zlib")).is_err());
+        assert!(decode_base64("AQAA", None).is_err());
+    }
+
+    #[test]
+    fn xml_data_rejects_invalid_gid() {
+        let tmx = |gid: &str| parse_tmx(&format!(r#"<map width="2" height="1" tilewidth="32" tileheight="32">
+            <layer name="ground"><data><tile gid="1"/><tile {}/></data></layer></map>"#, gid));
+
+        assert_eq!(tmx("").unwrap().layers[0].data, vec![1, 0]);
+        assert_eq!(tmx(r#"gid="2""#).unwrap().layers[0].data, vec![1, 2]);
+        assert_eq!(tmx(r#"gid="wall""#).err().unwrap(), "layer ground : invalid gid \"wall\"");
+    }
+}
@@ -0,0 +1,263 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::level::{MapElement, MapHazard, MapLiquid, MapPowerUp, MapSurface, MAP_EMPTY, MAP_PLAYER_START};
+
+pub const TILESET_DEFAULT_FILENAME: &str = "levels/tileset.toml";
+
+// ################################################################################################################
+// #                                         F I L E   F O R M A T                                                #
+// ################################################################################################################
+//
+// [[tiles]]
+// id = "1"                       # char used in levels/NNN.txt
+// name = "Wall1"
+// image = "images/tile1.png"
+// solid = true                   # optional, default false
+// one_way = true                 # optional, default false : platform blocking only from above
+// slope = [0.0, 1.0]             # optional : height of the ground (0..1 of the tile) on the left and right sides
+// climbable = true               # optional, default false : ladder (Up / Down to climb)
+// hazard = "hurt"                # optional : "hurt" (loses damage health points) or "kill" on contact
+// damage = 1                     # optional, default 1 : health points lost by a "hurt" hazard
+// checkpoint = true              # optional, default false : respawn point once touched
+// active_image = "images/x.png"  # image of an activated checkpoint (required by a checkpoint)
+// power_up = "dash"              # optional : ability picked up, "double_jump" or "dash"
+// liquid = { drag = 2.0 }        # optional : swimming in the tile, every value is optional
+//                                #   gravity_scale (0.3, negative floats up), drag (2.0, velocity lost / second),
+//                                #   stroke (220, speed of a swim stroke), breath_time (0 = no limit, seconds)
+// surface = { friction = 0.2 }   # optional : material of a solid / one-way tile, every value is optional
+//                                #   friction (1.0, < 1 slippery, > 1 sticky), conveyor (0, speed, < 0 to the left),
+//                                #   bounce (0, part of the falling speed given back on landing)
+// exit = true                    # optional, default false : go to next level
+//
+// [tiles.properties]             # optional, free values for designers
+// color = "grey"
+//
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TilesetFile {
+    #[serde(default)]
+    tiles: Vec<TileDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TileDefinition {
+    id: String,
+    name: String,
+    image: String,
+    #[serde(default)]
+    solid: bool,
+    #[serde(default)]
+    one_way: bool,
+    slope: Option<[f32; 2]>,
+    #[serde(default)]
+    climbable: bool,
+    hazard: Option<String>,
+    damage: Option<u32>,
+    #[serde(default)]
+    checkpoint: bool,
+    active_image: Option<String>,
+    power_up: Option<String>,
+    liquid: Option<TileLiquid>,
+    surface: Option<TileSurface>,
+    #[serde(default)]
+    exit: bool,
+    #[serde(default)]
+    properties: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TileLiquid {
+    gravity_scale: f32,
+    drag: f32,
+    stroke: f32,
+    breath_time: f32,
+}
+
+impl Default for TileLiquid {
+    fn default() -> Self {
+        let liquid = MapLiquid::default();
+        Self { gravity_scale: liquid.gravity_scale, drag: liquid.drag, stroke: liquid.stroke, breath_time: liquid.breath_time }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TileSurface {
+    friction: f32,
+    conveyor: f32,
+    bounce: f32,
+}
+
+impl Default for TileSurface {
+    fn default() -> Self {
+        let surface = MapSurface::default();
+        Self { friction: surface.friction, conveyor: surface.conveyor, bounce: surface.bounce }
+    }
+}
+
+/*
+ * load_tileset()
+ *
+ * @brief: Read a tileset definition file (TOML) and build the char -> MapElement table
+ */
+pub fn load_tileset(filename: &str) -> Result<HashMap<char, MapElement>, String> {
+    let content = fs::read_to_string(filename)
+        .map_err(|e| format!("Tileset : unable to read {} ({})", filename, e))?;
+
+    parse_tileset(&content).map_err(|e| format!("Tileset : {} in {}", e, filename))
+}
+
+/*
+ * parse_tileset()
+ *
+ * @brief: Convert a tileset definition (TOML) to MapElements, checking ids, names and images
+ */
+pub fn parse_tileset(content: &str) -> Result<HashMap<char, MapElement>, String> {
+    let file: TilesetFile = toml::from_str(content).map_err(|e| e.to_string())?;
+
+    let mut elements = HashMap::new();
+
+    for tile in file.tiles {
+        let mut chars = tile.id.chars();
+        let id = match (chars.next(), chars.next()) {
+            (Some(id), None) => id,
+            _ => return Err(format!("tile '{}' : id \"{}\" must be exactly one char", tile.name, tile.id)),
+        };
+
+        if id == MAP_EMPTY || id == MAP_PLAYER_START || id.is_whitespace() {
+            return Err(format!("tile '{}' : id '{}' is reserved", tile.name, id));
+        }
+        if tile.name.trim().is_empty() {
+            return Err(format!("tile '{}' : name is empty", id));
+        }
+        if !Path::new(&tile.image).is_file() {
+            return Err(format!("tile '{}' : image {} not found", tile.name, tile.image));
+        }
+        let hazard = match &tile.hazard {
+            Some(hazard) => Some(MapHazard::from_name(hazard, tile.damage.unwrap_or(1))
+                .ok_or(format!("tile '{}' : hazard must be \"hurt\" or \"kill\"", tile.name))?),
+            None if tile.damage.is_some() => return Err(format!("tile '{}' : damage without hazard", tile.name)),
+            None => None,
+        };
+        match &tile.active_image {
+            Some(image) if !tile.checkpoint => return Err(format!("tile '{}' : active image {} without checkpoint", tile.name, image)),
+            Some(image) if !Path::new(image).is_file() => return Err(format!("tile '{}' : active image {} not found", tile.name, image)),
+            None if tile.checkpoint => return Err(format!("tile '{}' : checkpoint without active image", tile.name)),
+            _ => {},
+        }
+        let power_up = match &tile.power_up {
+            Some(power_up) => Some(MapPowerUp::from_name(power_up)
+                .ok_or(format!("tile '{}' : power up must be \"double_jump\" or \"dash\"", tile.name))?),
+            None => None,
+        };
+        let liquid = tile.liquid.map(|liquid| MapLiquid {
+            gravity_scale: liquid.gravity_scale,
+            drag: liquid.drag,
+            stroke: liquid.stroke,
+            breath_time: liquid.breath_time,
+        });
+        let surface = tile.surface.map(|surface| MapSurface {
+            friction: surface.friction,
+            conveyor: surface.conveyor,
+            bounce: surface.bounce,
+        });
+        if elements.contains_key(&id) {
+            return Err(format!("tile '{}' : id '{}' already used", tile.name, id));
+        }
+
+        let element = MapElement {
+            name: tile.name,
+            filename: tile.image,
+            solid: tile.solid,
+            one_way: tile.one_way,
+            slope: tile.slope,
+            climbable: tile.climbable,
+            hazard,
+            checkpoint: tile.checkpoint,
+            active_filename: tile.active_image,
+            power_up,
+            liquid,
+            surface,
+            exit: tile.exit,
+            properties: tile.properties,
+        };
+        element.validate().map_err(|e| format!("tile '{}' : {}", element.name, e))?;
+
+        elements.insert(id, element);
+    }
+
+    Ok(elements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_tileset_reads_tiles() {
+        let elements = parse_tileset(r#"
+            [[tiles]]
+            id = "1"
+            name = "Wall"
+            image = "images/tile1.png"
+            solid = true
+
+            [[tiles]]
+            id = "-"
+            name = "Platform"
+            image = "images/platform.png"
+            one_way = true
+            [tiles.properties]
+            color = "grey"
+        "#).unwrap();
+
+        assert_eq!(elements.len(), 2);
+        assert!(elements[&'1'].solid && !elements[&'1'].one_way);
+        assert!(elements[&'-'].one_way && !elements[&'-'].solid);
+        assert_eq!(elements[&'-'].properties["color"], "grey");
+    }
+
+    #[test]
+    fn parse_tileset_rejects_duplicate_id() {
+        let error = parse_tileset(r#"
+            [[tiles]]
+            id = "1"
+            name = "Wall"
+            image = "images/tile1.png"
+
+            [[tiles]]
+            id = "1"
+            name = "Other"
+            image = "images/tile1.png"
+        "#).unwrap_err();
+
+        assert_eq!(error, "tile 'Other' : id '1' already used");
+    }
+
+    #[test]
+    fn parse_tileset_rejects_unknown_field() {
+        let error = parse_tileset(r#"
+            [[tiles]]
+            id = "1"
+            name = "Wall"
+            image = "images/tile1.png"
+            solide = true
+        "#).unwrap_err();
+
+        assert!(error.contains("unknown field `solide`"), "{}", error);
+    }
+
+    #[test]
+    fn parse_tileset_rejects_bad_id_or_image() {
+        let tile = |id: &str, image: &str| parse_tileset(&format!("[[tiles]]\nid = \"{}\"\nname = \"Tile\"\nimage = \"{}\"", id, image));
+
+        assert_eq!(tile("12", "images/tile1.png").unwrap_err(), "tile 'Tile' : id \"12\" must be exactly one char");
+        assert_eq!(tile("P", "images/tile1.png").unwrap_err(), "tile 'Tile' : id 'P' is reserved");
+        assert_eq!(tile("0", "images/tile1.png").unwrap_err(), "tile 'Tile' : id '0' is reserved");
+        assert_eq!(tile("x", "images/missing.png").unwrap_err(), "tile 'Tile' : image images/missing.png not found");
+    }
+}